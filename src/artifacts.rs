@@ -0,0 +1,386 @@
+use crate::{
+    executor::service_path,
+    utils::{env_var_name, parse_env},
+    Error, Executor, Result, ServiceStep,
+};
+use std::collections::BTreeMap;
+use std::fs::{copy, create_dir_all, read_dir, read_to_string, remove_dir_all, rename, write};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Environment variable containing the artifacts folder
+pub const ARTIFACTS_DIR_VAR: &str = "ORCS_ARTIFACTS_DIR";
+/// Environment variable containing the file for key/value outputs
+pub const OUTPUT_FILE_VAR: &str = "ORCS_OUTPUT";
+/// Prefix for the environment variables containing the key/value outputs of
+/// dependencies
+pub const OUTPUT_VAR_PREFIX: &str = "ORCS_OUTPUT_";
+/// Folder within the artifacts folder for key/value output files
+const VALUES_FOLDER: &str = ".values";
+
+/// Storage for the outputs declared by step:service pairs
+///
+/// After a successful run, the outputs of a step:service pair are copied to
+/// `<path>/<step>/<service>`, keeping their path relative to the service
+/// folder. Scripts find this folder through the `ORCS_ARTIFACTS_DIR`
+/// environment variable, e.g. `$ORCS_ARTIFACTS_DIR/build/api/dist`.
+///
+/// Step:service pairs can also produce key/value outputs, by writing
+/// `key=value` lines to the file in the `ORCS_OUTPUT` environment variable.
+/// Pairs that depend on them receive these values as environment variables,
+/// e.g. `ORCS_OUTPUT_BUILD_API_DIGEST` for the `digest` key of `build:api`.
+/// These values are only replaced when the run script of the pair runs, so
+/// that pairs that are up to date keep the values of their last run.
+pub struct ArtifactStore {
+    /// Root folder of the project
+    root: PathBuf,
+
+    /// Folder containing the artifacts
+    path: PathBuf,
+}
+
+impl ArtifactStore {
+    /// Create a new store for the project at `root`, keeping artifacts in
+    /// `path`
+    pub fn new<P, Q>(root: P, path: Q) -> Self
+    where
+        P: Into<PathBuf>,
+        Q: Into<PathBuf>,
+    {
+        Self {
+            root: root.into(),
+            path: path.into(),
+        }
+    }
+
+    /// Folder containing the artifacts
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Folder containing the artifacts of a step:service pair
+    pub fn step_path(&self, step: &ServiceStep) -> PathBuf {
+        self.path.join(&step.step_name).join(&step.service_name)
+    }
+
+    /// File for the key/value outputs of a step:service pair
+    pub fn values_path(&self, step: &ServiceStep) -> PathBuf {
+        self.path
+            .join(VALUES_FOLDER)
+            .join(&step.step_name)
+            .join(format!("{}.env", step.service_name))
+    }
+
+    /// File receiving the key/value outputs of a step:service pair while its
+    /// scripts run
+    fn pending_values_path(&self, step: &ServiceStep) -> PathBuf {
+        self.path
+            .join(VALUES_FOLDER)
+            .join(&step.step_name)
+            .join(format!("{}.pending.env", step.service_name))
+    }
+
+    /// Environment variables for the scripts of a step:service pair to find
+    /// the artifacts and the key/value outputs file
+    pub fn env(&self, executor: &dyn Executor, step: &ServiceStep) -> BTreeMap<String, String> {
+        let mut env: BTreeMap<String, String> = Default::default();
+        env.insert(
+            ARTIFACTS_DIR_VAR.to_string(),
+            executor.script_path(&self.path).to_string_lossy().into(),
+        );
+        env.insert(
+            OUTPUT_FILE_VAR.to_string(),
+            executor
+                .script_path(&self.pending_values_path(step))
+                .to_string_lossy()
+                .into(),
+        );
+        env
+    }
+
+    /// Create an empty key/value outputs file for the scripts of a
+    /// step:service pair
+    ///
+    /// This doesn't change the values of the previous run until
+    /// `commit_values` is called.
+    pub fn prepare_values(&self, step: &ServiceStep) -> Result<()> {
+        let path = self.pending_values_path(step);
+        create_dir_all(path.parent().expect("missing values file parent"))
+            .and_then(|_| write(&path, ""))
+            .map_err(|source| Error::CannotAccessValuesFile { path, source })
+    }
+
+    /// Replace the key/value outputs of a step:service pair with the ones
+    /// written by its scripts since `prepare_values`
+    pub fn commit_values(&self, step: &ServiceStep) -> Result<()> {
+        let path = self.values_path(step);
+        rename(self.pending_values_path(step), &path)
+            .map_err(|source| Error::CannotAccessValuesFile { path, source })
+    }
+
+    /// Read the key/value outputs of a step:service pair
+    ///
+    /// The file uses the same format as dotenv files.
+    pub fn read_values(&self, step: &ServiceStep) -> Result<BTreeMap<String, String>> {
+        let path = self.values_path(step);
+        let data = match read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Default::default()),
+            Err(source) => return Err(Error::CannotAccessValuesFile { path, source }),
+        };
+
+        parse_env(&data).map_err(|line| Error::InvalidOutputValue {
+            name: step.name.clone(),
+            line,
+        })
+    }
+
+    /// Collect the outputs of a step:service pair
+    ///
+    /// This replaces the artifacts from previous runs of the pair.
+    pub fn collect(&self, step: &ServiceStep) -> Result<()> {
+        let service_path = service_path(&self.root, &step.service_name);
+        let step_path = self.step_path(step);
+        remove_path(&step_path)?;
+
+        for output in step.outputs() {
+            let relative = Path::new(output);
+            if !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+            {
+                return Err(Error::InvalidOutputPath {
+                    name: step.name.clone(),
+                    path: output.clone(),
+                });
+            }
+
+            let source = service_path.join(relative);
+            if !source.exists() {
+                return Err(Error::MissingOutput {
+                    name: step.name.clone(),
+                    path: output.clone(),
+                });
+            }
+            let target = step_path.join(relative);
+            copy_path(&source, &target).map_err(|source| Error::CannotCopyArtifacts {
+                path: target,
+                source,
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Name of the environment variable for a key/value output of a dependency
+pub(crate) fn value_var(dependency: &str, key: &str) -> String {
+    format!(
+        "{}{}_{}",
+        OUTPUT_VAR_PREFIX,
+        env_var_name(dependency),
+        env_var_name(key)
+    )
+}
+
+/// Replace the content of `target` with a copy of `source`
+///
+/// If `source` doesn't exist, this only removes `target`.
+pub(crate) fn replace_path(source: &Path, target: &Path) -> Result<()> {
+    remove_path(target)?;
+    if source.exists() {
+        copy_path(source, target).map_err(|err| Error::CannotCopyArtifacts {
+            path: target.to_path_buf(),
+            source: err,
+        })?;
+    }
+    Ok(())
+}
+
+/// Remove a folder and its content, if it exists
+fn remove_path(path: &Path) -> Result<()> {
+    match remove_dir_all(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Error::CannotCopyArtifacts {
+            path: path.to_path_buf(),
+            source: err,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Copy a file or a folder recursively
+fn copy_path(source: &Path, target: &Path) -> io::Result<()> {
+    if source.is_dir() {
+        create_dir_all(target)?;
+        for entry in read_dir(source)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &target.join(entry.file_name()))?;
+        }
+    } else {
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent)?;
+        }
+        copy(source, target)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServiceStepConfig;
+    use crate::test_utils::build_step;
+    use crate::LocalExecutor;
+    use std::fs::{read_to_string, write};
+    use tempfile::tempdir;
+
+    fn outputs_config(outputs: &[&str]) -> ServiceStepConfig {
+        ServiceStepConfig {
+            outputs: outputs.iter().map(|output| output.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn collect() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let service = service_path(root, "my-service");
+        create_dir_all(service.join("dist/bin")).expect("failed to create folder");
+        write(service.join("dist/bin/app"), "app").expect("failed to write file");
+        write(service.join("report.txt"), "report").expect("failed to write file");
+
+        let store = ArtifactStore::new(root, root.join("artifacts"));
+        let step_path = root.join("artifacts/my-step/my-service");
+        create_dir_all(step_path.join("stale")).expect("failed to create folder");
+
+        store
+            .collect(&build_step(
+                "my-service",
+                "my-step",
+                &outputs_config(&["dist", "./report.txt"]),
+            ))
+            .expect("failed to collect outputs");
+
+        let read = |path: &str| read_to_string(step_path.join(path)).expect("failed to read file");
+        assert_eq!(read("dist/bin/app"), "app");
+        assert_eq!(read("report.txt"), "report");
+        assert!(!step_path.join("stale").exists());
+    }
+
+    #[test]
+    fn collect_missing() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        create_dir_all(service_path(root, "my-service")).expect("failed to create folder");
+        let store = ArtifactStore::new(root, root.join("artifacts"));
+
+        match store.collect(&build_step(
+            "my-service",
+            "my-step",
+            &outputs_config(&["dist"]),
+        )) {
+            Err(Error::MissingOutput { name, path }) => {
+                assert_eq!(name, "my-step:my-service");
+                assert_eq!(path, "dist");
+            }
+            _ => panic!("expected a missing output error"),
+        }
+    }
+
+    #[test]
+    fn collect_invalid() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let store = ArtifactStore::new(root, root.join("artifacts"));
+
+        for output in ["../other-service", "/etc/passwd"].iter() {
+            match store.collect(&build_step(
+                "my-service",
+                "my-step",
+                &outputs_config(&[output]),
+            )) {
+                Err(Error::InvalidOutputPath { path, .. }) => assert_eq!(path, *output),
+                _ => panic!("expected an invalid output error"),
+            }
+        }
+    }
+
+    #[test]
+    fn env() {
+        let store = ArtifactStore::new("/project", "/project/.orcs/artifacts");
+        let executor = LocalExecutor::new("/project");
+
+        let env = store.env(
+            &executor,
+            &build_step("my-service", "my-step", &outputs_config(&[])),
+        );
+        assert_eq!(
+            env.get(ARTIFACTS_DIR_VAR).map(String::as_str),
+            Some("/project/.orcs/artifacts")
+        );
+        assert_eq!(
+            env.get(OUTPUT_FILE_VAR).map(String::as_str),
+            Some("/project/.orcs/artifacts/.values/my-step/my-service.pending.env")
+        );
+    }
+
+    #[test]
+    fn values() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let store = ArtifactStore::new(root, root.join("artifacts"));
+        let step = build_step("my-service", "my-step", &outputs_config(&[]));
+
+        // Values are only replaced once committed
+        store
+            .prepare_values(&step)
+            .expect("failed to prepare values");
+        write(store.pending_values_path(&step), "previous=value").expect("failed to write file");
+        store.commit_values(&step).expect("failed to commit values");
+        store
+            .prepare_values(&step)
+            .expect("failed to prepare values");
+        assert_eq!(
+            store
+                .read_values(&step)
+                .expect("failed to read values")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [(String::from("previous"), String::from("value"))]
+        );
+        store.commit_values(&step).expect("failed to commit values");
+        assert!(store
+            .read_values(&step)
+            .expect("failed to read values")
+            .is_empty());
+
+        write(
+            store.values_path(&step),
+            "# comment\ndigest=sha256:abcd\n\ntag=v1=latest\ndigest=sha256:ef01\n",
+        )
+        .expect("failed to write file");
+        let values = store.read_values(&step).expect("failed to read values");
+        assert_eq!(
+            values.into_iter().collect::<Vec<_>>(),
+            [
+                (String::from("digest"), String::from("sha256:ef01")),
+                (String::from("tag"), String::from("v1=latest"))
+            ]
+        );
+
+        write(store.values_path(&step), "not a value\n").expect("failed to write file");
+        match store.read_values(&step) {
+            Err(Error::InvalidOutputValue { line, .. }) => assert_eq!(line, "not a value"),
+            _ => panic!("expected an invalid output value error"),
+        }
+    }
+
+    #[test]
+    fn value_var_name() {
+        assert_eq!(
+            value_var("build:my-api", "image-digest"),
+            "ORCS_OUTPUT_BUILD_MY_API_IMAGE_DIGEST"
+        );
+    }
+}
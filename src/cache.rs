@@ -0,0 +1,387 @@
+use crate::{artifacts::replace_path, Error, Result, StepStatus};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read, remove_dir_all, rename, write};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the file containing an entry in a `SharedCache`
+const SHARED_ENTRY_FILENAME: &str = "entry.json";
+/// Folder containing the outputs of an entry in a `SharedCache`
+const SHARED_OUTPUTS_FOLDER: &str = "outputs";
+/// Folder to prepare entries in for a `SharedCache`
+const SHARED_TMP_FOLDER: &str = "tmp";
+
+/// Storage for the results of successful step:service pairs
+///
+/// Entries are keyed by the fingerprint of the step:service pair, and can
+/// contain the outputs declared by the pair. Backends are shared between
+/// worker threads when running step:service pairs concurrently.
+pub trait CacheBackend: Sync {
+    /// Retrieve the entry for a fingerprint, if any
+    ///
+    /// When an entry is found and `outputs` is set, the outputs stored with
+    /// the entry replace the content of that folder.
+    fn get(&self, fingerprint: &str, outputs: Option<&Path>) -> Result<Option<CacheEntry>>;
+
+    /// Store the entry for a fingerprint
+    ///
+    /// When `outputs` is set, the content of that folder is stored with the
+    /// entry.
+    fn put(&self, fingerprint: &str, entry: &CacheEntry, outputs: Option<&Path>) -> Result<()>;
+}
+
+/// Result of a successful step:service pair, stored in a cache
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Name of the pair in 'step:service' format
+    pub name: String,
+
+    /// Status of the run that created this entry
+    pub status: StepStatus,
+
+    /// Key/value outputs of the run that created this entry
+    #[serde(default)]
+    pub values: BTreeMap<String, String>,
+}
+
+/// Cache of step:service pair results on the local machine
+///
+/// Entries are stored as JSON files in a folder, named after the fingerprint
+/// of the step:service pair, with their outputs in a folder of the same name.
+pub struct LocalCache {
+    path: PathBuf,
+}
+
+impl LocalCache {
+    /// Create a new cache storing entries in `path`
+    ///
+    /// The folder is created when storing the first entry.
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn entry_path(&self, fingerprint: &str) -> PathBuf {
+        self.path.join(format!("{}.json", fingerprint))
+    }
+
+    fn outputs_path(&self, fingerprint: &str) -> PathBuf {
+        self.path.join(fingerprint)
+    }
+}
+
+impl CacheBackend for LocalCache {
+    fn get(&self, fingerprint: &str, outputs: Option<&Path>) -> Result<Option<CacheEntry>> {
+        let entry = read_entry(self.entry_path(fingerprint))?;
+        if let (Some(_), Some(outputs)) = (&entry, outputs) {
+            replace_path(&self.outputs_path(fingerprint), outputs)?;
+        }
+        Ok(entry)
+    }
+
+    fn put(&self, fingerprint: &str, entry: &CacheEntry, outputs: Option<&Path>) -> Result<()> {
+        // Store the outputs first, as the entry file marks complete entries
+        if let Some(outputs) = outputs {
+            replace_path(outputs, &self.outputs_path(fingerprint))?;
+        }
+
+        let path = self.entry_path(fingerprint);
+        let data = serde_json::to_vec(entry).expect("failed to serialize cache entry");
+        create_dir_all(&self.path)
+            .and_then(|_| write(&path, data))
+            .map_err(|source| Error::CannotWriteCache { path, source })
+    }
+}
+
+/// Cache of step:service pair results in a folder shared between machines
+///
+/// This is meant for folders such as network mounts or CI cache folders,
+/// which multiple runners read from and write to at the same time. Each
+/// entry is a folder named after the fingerprint, prepared in a temporary
+/// location and then moved in place, so that runners never see incomplete
+/// entries. When two runners store the same fingerprint, the first one wins.
+pub struct SharedCache {
+    path: PathBuf,
+}
+
+impl SharedCache {
+    /// Create a new cache storing entries in `path`
+    ///
+    /// The folder is created when storing the first entry.
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Folder containing the entry for a fingerprint
+    ///
+    /// Entries are spread into sub-folders based on the start of the
+    /// fingerprint, to keep folders small.
+    fn entry_dir(&self, fingerprint: &str) -> PathBuf {
+        let prefix = fingerprint.get(..2).unwrap_or(fingerprint);
+        self.path.join(prefix).join(fingerprint)
+    }
+
+    /// Unique temporary folder to prepare an entry in
+    fn tmp_dir(&self, fingerprint: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos());
+        self.path.join(SHARED_TMP_FOLDER).join(format!(
+            "{}.{}.{}.{}",
+            fingerprint,
+            std::process::id(),
+            nanos,
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ))
+    }
+}
+
+impl CacheBackend for SharedCache {
+    fn get(&self, fingerprint: &str, outputs: Option<&Path>) -> Result<Option<CacheEntry>> {
+        let entry_dir = self.entry_dir(fingerprint);
+        let entry = read_entry(entry_dir.join(SHARED_ENTRY_FILENAME))?;
+        if let (Some(_), Some(outputs)) = (&entry, outputs) {
+            replace_path(&entry_dir.join(SHARED_OUTPUTS_FOLDER), outputs)?;
+        }
+        Ok(entry)
+    }
+
+    fn put(&self, fingerprint: &str, entry: &CacheEntry, outputs: Option<&Path>) -> Result<()> {
+        let entry_dir = self.entry_dir(fingerprint);
+        if entry_dir.exists() {
+            return Ok(());
+        }
+
+        let tmp_dir = self.tmp_dir(fingerprint);
+        if let Some(outputs) = outputs {
+            replace_path(outputs, &tmp_dir.join(SHARED_OUTPUTS_FOLDER))?;
+        }
+        let data = serde_json::to_vec(entry).expect("failed to serialize cache entry");
+        create_dir_all(&tmp_dir)
+            .and_then(|_| write(tmp_dir.join(SHARED_ENTRY_FILENAME), data))
+            .and_then(|_| create_dir_all(entry_dir.parent().expect("missing entry parent")))
+            .map_err(|source| Error::CannotWriteCache {
+                path: tmp_dir.clone(),
+                source,
+            })?;
+
+        // Another runner might have stored the same fingerprint in the
+        // meantime, in which case we keep theirs.
+        let result = rename(&tmp_dir, &entry_dir);
+        if result.is_err() {
+            let _ = remove_dir_all(&tmp_dir);
+        }
+        match result {
+            Err(_) if entry_dir.exists() => Ok(()),
+            result => result.map_err(|source| Error::CannotWriteCache {
+                path: entry_dir,
+                source,
+            }),
+        }
+    }
+}
+
+/// Read a cache entry from a file
+///
+/// Missing files are treated as missing entries.
+fn read_entry(path: PathBuf) -> Result<Option<CacheEntry>> {
+    let data = match read(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(source) => return Err(Error::CannotReadCache { path, source }),
+    };
+
+    // Treat corrupted entries as missing, they'll be overwritten by the next
+    // successful run.
+    Ok(serde_json::from_slice(&data).ok())
+}
+
+/// Builder for the fingerprint of a step:service pair
+///
+/// Each value is prefixed with its length, so that moving data from one
+/// value to the next changes the fingerprint.
+#[derive(Default)]
+pub(crate) struct Fingerprinter {
+    hasher: Sha256,
+}
+
+impl Fingerprinter {
+    /// Add a value to the fingerprint
+    pub fn add<T>(&mut self, value: T) -> &mut Self
+    where
+        T: AsRef<[u8]>,
+    {
+        let value = value.as_ref();
+        self.hasher.update((value.len() as u64).to_le_bytes());
+        self.hasher.update(value);
+        self
+    }
+
+    /// Compute the fingerprint as a hexadecimal string
+    pub fn finish(self) -> String {
+        self.hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn fingerprint() {
+        let fingerprint = |values: &[&str]| {
+            let mut fingerprinter = Fingerprinter::default();
+            for value in values {
+                fingerprinter.add(value);
+            }
+            fingerprinter.finish()
+        };
+
+        assert_eq!(fingerprint(&["a", "b"]).len(), 64);
+        assert_eq!(fingerprint(&["a", "b"]), fingerprint(&["a", "b"]));
+        assert_ne!(fingerprint(&["a", "b"]), fingerprint(&["ab", ""]));
+        assert_ne!(fingerprint(&["a", "b"]), fingerprint(&["b", "a"]));
+    }
+
+    #[test]
+    fn local_cache() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+        let cache = LocalCache::new(dir.path().join("cache"));
+        let entry = CacheEntry {
+            name: String::from("my-step:a"),
+            status: StepStatus::Succeeded,
+            values: Default::default(),
+        };
+
+        assert_eq!(cache.get("abcd", None).expect("failed to read cache"), None);
+        cache
+            .put("abcd", &entry, None)
+            .expect("failed to write cache");
+        assert_eq!(
+            cache.get("abcd", None).expect("failed to read cache"),
+            Some(entry)
+        );
+        assert_eq!(cache.get("dcba", None).expect("failed to read cache"), None);
+    }
+
+    #[test]
+    fn shared_cache() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+        let cache = SharedCache::new(dir.path());
+        let entry = |name: &str| CacheEntry {
+            name: name.to_string(),
+            status: StepStatus::Succeeded,
+            values: Default::default(),
+        };
+
+        assert_eq!(cache.get("abcd", None).expect("failed to read cache"), None);
+        cache
+            .put("abcd", &entry("my-step:a"), None)
+            .expect("failed to write cache");
+        assert!(dir.path().join("ab/abcd/entry.json").is_file());
+
+        // The first entry stored wins
+        cache
+            .put("abcd", &entry("my-step:b"), None)
+            .expect("failed to write cache");
+        assert_eq!(
+            cache.get("abcd", None).expect("failed to read cache"),
+            Some(entry("my-step:a"))
+        );
+
+        // Temporary folders are moved in place
+        let tmp_entries = std::fs::read_dir(dir.path().join("tmp"))
+            .expect("failed to read temporary folder")
+            .count();
+        assert_eq!(tmp_entries, 0);
+    }
+
+    #[test]
+    fn shared_cache_threads() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+        let cache = SharedCache::new(dir.path());
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let cache = &cache;
+                scope.spawn(move || {
+                    let entry = CacheEntry {
+                        name: format!("my-step:{}", i),
+                        status: StepStatus::Succeeded,
+                        values: Default::default(),
+                    };
+                    cache
+                        .put("abcd", &entry, None)
+                        .expect("failed to write cache");
+                });
+            }
+        });
+
+        assert!(cache
+            .get("abcd", None)
+            .expect("failed to read cache")
+            .is_some());
+    }
+
+    #[test]
+    fn cache_outputs() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+        let local_cache = LocalCache::new(dir.path().join("local"));
+        let shared_cache = SharedCache::new(dir.path().join("shared"));
+        let caches: [&dyn CacheBackend; 2] = [&local_cache, &shared_cache];
+        let entry = CacheEntry {
+            name: String::from("my-step:a"),
+            status: StepStatus::Succeeded,
+            values: Default::default(),
+        };
+
+        let outputs = dir.path().join("outputs");
+        std::fs::create_dir_all(outputs.join("dist")).expect("failed to create folder");
+        std::fs::write(outputs.join("dist/app"), "app").expect("failed to write file");
+        let restored = dir.path().join("restored");
+
+        for cache in caches.iter() {
+            cache
+                .put("abcd", &entry, Some(&outputs))
+                .expect("failed to write cache");
+
+            std::fs::create_dir_all(restored.join("stale")).expect("failed to create folder");
+            assert!(cache
+                .get("abcd", Some(&restored))
+                .expect("failed to read cache")
+                .is_some());
+            assert_eq!(
+                std::fs::read_to_string(restored.join("dist/app")).expect("failed to read file"),
+                "app"
+            );
+            assert!(!restored.join("stale").exists());
+
+            // Missing entries leave the folder untouched
+            assert!(cache
+                .get("dcba", Some(&restored))
+                .expect("failed to read cache")
+                .is_none());
+            assert!(restored.join("dist/app").exists());
+        }
+    }
+}
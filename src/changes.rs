@@ -0,0 +1,316 @@
+use crate::{Error, Result};
+use git2::{DiffOptions, Repository, StatusOptions, Tree};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Base revision for change detection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffBase {
+    /// Any git revision (e.g. `main`, `v1.0.0`, `HEAD~1` or a commit hash)
+    Rev(String),
+    /// Merge base between `HEAD` and the given revision
+    MergeBase(String),
+}
+
+/// Target for change detection, compared against a `DiffBase`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffTarget {
+    /// Any git revision
+    Rev(String),
+    /// The working tree, including staged, unstaged and untracked files
+    WorkTree,
+}
+
+/// Find all files that changed between `base` and `target` in a repository
+///
+/// Paths are relative to the root of the repository. For renamed files, both
+/// the old and new paths are returned.
+pub fn changed_files<P>(path: P, base: &DiffBase, target: &DiffTarget) -> Result<BTreeSet<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let repo = Repository::open(path).map_err(|source| Error::ProjectIsNotGitRepo {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let base_tree = match base {
+        DiffBase::Rev(rev) => rev_tree(&repo, rev)?,
+        DiffBase::MergeBase(rev) => {
+            let head = rev_commit_id(&repo, "HEAD")?;
+            let other = rev_commit_id(&repo, rev)?;
+            let merge_base =
+                repo.merge_base(head, other)
+                    .map_err(|source| Error::CannotResolveRevision {
+                        rev: format!("merge-base(HEAD, {})", rev),
+                        source,
+                    })?;
+            rev_tree(&repo, &merge_base.to_string())?
+        }
+    };
+
+    let diff = match target {
+        DiffTarget::Rev(rev) => {
+            let target_tree = rev_tree(&repo, rev)?;
+            repo.diff_tree_to_tree(Some(&base_tree), Some(&target_tree), None)
+        }
+        DiffTarget::WorkTree => {
+            let mut opts = DiffOptions::new();
+            opts.include_untracked(true).recurse_untracked_dirs(true);
+            repo.diff_tree_to_workdir_with_index(Some(&base_tree), Some(&mut opts))
+        }
+    }
+    .map_err(|source| Error::CannotDiff { source })?;
+
+    let mut files: BTreeSet<PathBuf> = Default::default();
+    for delta in diff.deltas() {
+        for file in [delta.old_file(), delta.new_file()].iter() {
+            if let Some(path) = file.path() {
+                files.insert(path.to_path_buf());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// List all files of a repository that are not ignored by git
+///
+/// This contains the files tracked by git, as well as the untracked files
+/// that are not ignored. Paths are relative to the root of the repository
+/// and sorted.
+pub(crate) fn input_files<P>(path: P) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let repo = Repository::open(path).map_err(|source| Error::ProjectIsNotGitRepo {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let index = repo
+        .index()
+        .map_err(|source| Error::CannotListTrackedFiles { source })?;
+
+    let mut files: BTreeSet<PathBuf> = index
+        .iter()
+        .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).as_ref()))
+        .collect();
+
+    // Untracked files
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+    let statuses = repo
+        .statuses(Some(&mut opts))
+        .map_err(|source| Error::CannotListTrackedFiles { source })?;
+    files.extend(
+        statuses
+            .iter()
+            .filter(|entry| entry.status().is_wt_new())
+            .filter_map(|entry| entry.path().map(PathBuf::from)),
+    );
+
+    Ok(files.into_iter().collect())
+}
+
+/// Resolve a revision into a tree
+fn rev_tree<'a>(repo: &'a Repository, rev: &str) -> Result<Tree<'a>> {
+    repo.revparse_single(rev)
+        .and_then(|object| object.peel_to_tree())
+        .map_err(|source| Error::CannotResolveRevision {
+            rev: rev.to_string(),
+            source,
+        })
+}
+
+/// Resolve a revision into a commit ID
+fn rev_commit_id(repo: &Repository, rev: &str) -> Result<git2::Oid> {
+    repo.revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .map(|commit| commit.id())
+        .map_err(|source| Error::CannotResolveRevision {
+            rev: rev.to_string(),
+            source,
+        })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use git2::Signature;
+    use std::fs::{create_dir_all, remove_file, write};
+    use tempfile::tempdir;
+
+    /// Commit all files in the working tree and return the commit ID
+    pub(crate) fn commit_all(repo: &Repository, message: &str) -> String {
+        let mut index = repo.index().expect("failed to get index");
+        index
+            .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+            .expect("failed to add files");
+        index
+            .update_all(["*"].iter(), None)
+            .expect("failed to update index");
+        index.write().expect("failed to write index");
+        let tree_id = index.write_tree().expect("failed to write tree");
+        let tree = repo.find_tree(tree_id).expect("failed to find tree");
+
+        let signature = Signature::now("orcs", "orcs@example.com").expect("failed to sign");
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .expect("failed to commit")
+        .to_string()
+    }
+
+    fn write_file<P>(root: P, path: &str, data: &str)
+    where
+        P: AsRef<Path>,
+    {
+        let path = root.as_ref().join(path);
+        create_dir_all(path.parent().expect("failed to get parent folder"))
+            .expect("failed to create folder");
+        write(path, data).expect("failed to write file");
+    }
+
+    #[test]
+    fn changed_files_revs() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+        let repo = Repository::init(dir.path()).expect("failed to create a git repository");
+
+        write_file(dir.path(), "a/file.txt", "a");
+        write_file(dir.path(), "b/file.txt", "b");
+        let first = commit_all(&repo, "first");
+
+        write_file(dir.path(), "b/file.txt", "bb");
+        write_file(dir.path(), "c/file.txt", "c");
+        let second = commit_all(&repo, "second");
+
+        let files = changed_files(dir.path(), &DiffBase::Rev(first), &DiffTarget::Rev(second))
+            .expect("failed to get changed files");
+
+        assert_eq!(
+            files.into_iter().collect::<Vec<_>>(),
+            [PathBuf::from("b/file.txt"), PathBuf::from("c/file.txt")]
+        );
+    }
+
+    #[test]
+    fn changed_files_worktree() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+        let repo = Repository::init(dir.path()).expect("failed to create a git repository");
+
+        write_file(dir.path(), "a/file.txt", "a");
+        write_file(dir.path(), "b/file.txt", "b");
+        commit_all(&repo, "first");
+
+        remove_file(dir.path().join("a/file.txt")).expect("failed to remove file");
+        write_file(dir.path(), "c/d/file.txt", "c");
+
+        let files = changed_files(
+            dir.path(),
+            &DiffBase::Rev(String::from("HEAD")),
+            &DiffTarget::WorkTree,
+        )
+        .expect("failed to get changed files");
+
+        assert_eq!(
+            files.into_iter().collect::<Vec<_>>(),
+            [PathBuf::from("a/file.txt"), PathBuf::from("c/d/file.txt")]
+        );
+    }
+
+    #[test]
+    fn changed_files_merge_base() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+        let repo = Repository::init(dir.path()).expect("failed to create a git repository");
+
+        write_file(dir.path(), "a/file.txt", "a");
+        let first = commit_all(&repo, "first");
+        let first_commit = repo
+            .find_commit(git2::Oid::from_str(&first).expect("failed to parse oid"))
+            .expect("failed to find commit");
+        repo.branch("main-branch", &first_commit, false)
+            .expect("failed to create branch");
+
+        // Changes on 'main-branch' after the merge base are ignored
+        repo.set_head("refs/heads/main-branch")
+            .expect("failed to set head");
+        write_file(dir.path(), "b/file.txt", "b");
+        commit_all(&repo, "main");
+
+        let feature = repo
+            .branch("feature", &first_commit, false)
+            .expect("failed to create branch");
+        repo.set_head(feature.get().name().expect("failed to get branch name"))
+            .expect("failed to set head");
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .expect("failed to checkout");
+        write_file(dir.path(), "c/file.txt", "c");
+        commit_all(&repo, "feature");
+
+        let files = changed_files(
+            dir.path(),
+            &DiffBase::MergeBase(String::from("main-branch")),
+            &DiffTarget::Rev(String::from("HEAD")),
+        )
+        .expect("failed to get changed files");
+
+        assert_eq!(
+            files.into_iter().collect::<Vec<_>>(),
+            [PathBuf::from("c/file.txt")]
+        );
+    }
+
+    #[test]
+    fn changed_files_unknown_rev() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+        let repo = Repository::init(dir.path()).expect("failed to create a git repository");
+        write_file(dir.path(), "a/file.txt", "a");
+        commit_all(&repo, "first");
+
+        match changed_files(
+            dir.path(),
+            &DiffBase::Rev(String::from("does-not-exist")),
+            &DiffTarget::WorkTree,
+        ) {
+            Err(Error::CannotResolveRevision { rev, .. }) => assert_eq!(rev, "does-not-exist"),
+            _ => panic!("expected a revision error"),
+        }
+    }
+
+    #[test]
+    fn input_files_untracked() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+        let repo = Repository::init(dir.path()).expect("failed to create a git repository");
+
+        write_file(dir.path(), "b/file.txt", "b");
+        write_file(dir.path(), "a/file.txt", "a");
+        write_file(dir.path(), ".gitignore", "ignored.txt");
+        commit_all(&repo, "first");
+        write_file(dir.path(), "c/file.txt", "c");
+        write_file(dir.path(), "c/ignored.txt", "c");
+
+        let files = input_files(dir.path()).expect("failed to get input files");
+
+        // Untracked files are listed, unless they are ignored
+        assert_eq!(
+            files,
+            [
+                PathBuf::from(".gitignore"),
+                PathBuf::from("a/file.txt"),
+                PathBuf::from("b/file.txt"),
+                PathBuf::from("c/file.txt")
+            ]
+        );
+    }
+}
@@ -4,8 +4,8 @@ mod script;
 mod service;
 
 pub use {
-    project::ProjectConfig,
-    recipe::{RecipeConfig, RecipeStepConfig},
+    project::{ProjectConfig, ProjectOptions, ProjectStepConfig, StepOnChanged},
+    recipe::{RecipeConfig, RecipeParamConfig, RecipeStepConfig},
     script::ScriptConfig,
-    service::{ServiceConfig, ServiceStepConfig},
+    service::{RecipeRef, ServiceConfig, ServiceStepConfig},
 };
@@ -1,7 +1,10 @@
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+#[allow(clippy::redundant_static_lifetimes)]
 const DEFAULT_CONTAINER_IMAGE: &'static str = "ubuntu:20.04";
+const DEFAULT_CACHE_DIR: &str = ".orcs/cache";
+const DEFAULT_ARTIFACTS_DIR: &str = ".orcs/artifacts";
 
 /// Representation of the project configuration file
 #[derive(Debug, Default, Deserialize)]
@@ -13,13 +16,26 @@ pub struct ProjectConfig {
 
     #[serde(default)]
     pub options: ProjectOptions,
+
+    /// Environment variables for all step:service pairs
+    ///
+    /// Recipes and services can override these with their own variables.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Dotenv file with environment variables for all step:service pairs,
+    /// relative to the project root
+    ///
+    /// Variables in `env` take precedence over the ones in this file.
+    #[serde(default)]
+    pub env_file: Option<String>,
 }
 
 /// Represent the configuration for a project step
 ///
 /// This doesn't contain any default actions, but just the dependencies from
 /// that step to other steps.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct ProjectStepConfig {
     /// List of dependencies for that step.
     ///
@@ -39,9 +55,28 @@ pub struct ProjectStepConfig {
     /// service.
     #[serde(default)]
     pub on_changed: StepOnChanged,
+
+    /// Whether successful runs of this step can be reused when nothing
+    /// changed since.
+    ///
+    /// This should be disabled for steps with side effects outside of the
+    /// project, such as deployments.
+    #[serde(default = "default_cache")]
+    pub cache: bool,
+}
+
+impl Default for ProjectStepConfig {
+    fn default() -> Self {
+        Self {
+            depends_on: Default::default(),
+            skip_run: Default::default(),
+            on_changed: Default::default(),
+            cache: default_cache(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
 pub enum StepOnChanged {
     /// Don't do anything for this step on changed
     #[serde(rename = "skip")]
@@ -54,6 +89,7 @@ pub enum StepOnChanged {
     Run,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for StepOnChanged {
     fn default() -> Self {
         Self::Run
@@ -61,13 +97,63 @@ impl Default for StepOnChanged {
 }
 
 /// All options and flags for a project
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize)]
 pub struct ProjectOptions {
     /// Name of the container image to use
     ///
     /// By default, we use the `ubuntu:20.04` container image.
     #[serde(default = "default_container_image")]
     pub container_image: String,
+
+    /// Whether a change to the project configuration file marks all services
+    /// as changed
+    ///
+    /// This is enabled by default, as changes to steps can impact every
+    /// service.
+    #[serde(default = "default_config_changes_all")]
+    pub config_changes_all: bool,
+
+    /// Maximum number of step:service pairs to run concurrently
+    ///
+    /// By default, this is the number of CPUs available.
+    #[serde(default = "default_jobs")]
+    pub jobs: usize,
+
+    /// Folder storing the results of successful step:service pairs,
+    /// relative to the project root
+    ///
+    /// By default, this is `.orcs/cache`.
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: String,
+
+    /// Folder shared between machines storing the results of successful
+    /// step:service pairs, such as a network mount or a CI cache folder
+    ///
+    /// Relative paths start from the project root. By default, no shared
+    /// cache is used.
+    #[serde(default)]
+    pub shared_cache_dir: Option<String>,
+
+    /// Folder storing the outputs of step:service pairs, relative to the
+    /// project root
+    ///
+    /// By default, this is `.orcs/artifacts`. This must be within the
+    /// project, so that scripts running in containers can access it.
+    #[serde(default = "default_artifacts_dir")]
+    pub artifacts_dir: String,
+}
+
+impl Default for ProjectOptions {
+    fn default() -> Self {
+        Self {
+            container_image: default_container_image(),
+            config_changes_all: default_config_changes_all(),
+            jobs: default_jobs(),
+            cache_dir: default_cache_dir(),
+            shared_cache_dir: None,
+            artifacts_dir: default_artifacts_dir(),
+        }
+    }
 }
 
 #[inline]
@@ -75,23 +161,59 @@ fn default_container_image() -> String {
     String::from(DEFAULT_CONTAINER_IMAGE)
 }
 
+#[inline]
+fn default_config_changes_all() -> bool {
+    true
+}
+
+#[inline]
+fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+}
+
+#[inline]
+fn default_cache_dir() -> String {
+    String::from(DEFAULT_CACHE_DIR)
+}
+
+#[inline]
+fn default_artifacts_dir() -> String {
+    String::from(DEFAULT_ARTIFACTS_DIR)
+}
+
+#[inline]
+fn default_cache() -> bool {
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn deserialize() {
         let data = "
         name = \"my-project\"
+        env_file = \".env\"
+
+        [env]
+        REGION = \"eu-west-1\"
 
         [options]
 
         container_image = \"my-container\"
+        config_changes_all = false
+        jobs = 4
+        cache_dir = \"my-cache\"
+        shared_cache_dir = \"/mnt/cache\"
+        artifacts_dir = \"my-artifacts\"
 
         [steps.my-step]
         depends_on = [\"a\", \"b\", \"c\"]
         skip_run = true
         on_changed = \"run\"
+        cache = false
         ";
 
         let config: ProjectConfig = toml::from_str(data).expect("failed to deserialize data");
@@ -99,8 +221,23 @@ mod tests {
         // Name
         assert_eq!(config.name, "my-project");
 
+        // Environment
+        assert_eq!(
+            config.env.get("REGION").map(String::as_str),
+            Some("eu-west-1")
+        );
+        assert_eq!(config.env_file.as_deref(), Some(".env"));
+
         // Options
         assert_eq!(config.options.container_image, "my-container");
+        assert!(!config.options.config_changes_all);
+        assert_eq!(config.options.jobs, 4);
+        assert_eq!(config.options.cache_dir, "my-cache");
+        assert_eq!(
+            config.options.shared_cache_dir,
+            Some(String::from("/mnt/cache"))
+        );
+        assert_eq!(config.options.artifacts_dir, "my-artifacts");
 
         // Steps
         assert!(config.steps.contains_key("my-step"));
@@ -108,18 +245,35 @@ mod tests {
         assert_eq!(step.depends_on, ["a", "b", "c"]);
         assert_eq!(step.skip_run, true);
         assert_eq!(step.on_changed, StepOnChanged::Run);
+        assert!(!step.cache);
+    }
+
+    #[test]
+    fn deserialize_default_options() {
+        let config: ProjectConfig =
+            toml::from_str("name = \"my-project\"").expect("failed to deserialize data");
+
+        assert_eq!(config.options.container_image, DEFAULT_CONTAINER_IMAGE);
+        assert!(config.options.config_changes_all);
+        assert!(config.options.jobs >= 1);
+        assert_eq!(config.options.cache_dir, DEFAULT_CACHE_DIR);
+        assert_eq!(config.options.shared_cache_dir, None);
+        assert_eq!(config.options.artifacts_dir, DEFAULT_ARTIFACTS_DIR);
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn default_step() {
         let step: ProjectStepConfig = Default::default();
 
         assert_eq!(step.depends_on, Vec::new() as Vec<String>);
         assert_eq!(step.skip_run, false);
         assert_eq!(step.on_changed, StepOnChanged::Run);
+        assert!(step.cache);
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn deserialize_step() {
         let data = "
             depends_on = [\"a\", \"b\", \"c\"]
@@ -131,6 +285,7 @@ mod tests {
         assert_eq!(step.depends_on, ["a", "b", "c"]);
         assert_eq!(step.skip_run, true);
         assert_eq!(step.on_changed, StepOnChanged::CheckFirst);
+        assert!(step.cache);
     }
 
     #[test]
@@ -1,19 +1,59 @@
 use super::ScriptConfig;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(Default, Deserialize)]
+#[derive(Clone, Default, Deserialize)]
 pub struct RecipeConfig {
+    /// Names of the recipes this recipe extends
+    ///
+    /// Steps, parameters and variables from these recipes are used when this
+    /// recipe doesn't define them. As with the recipes of a service, the
+    /// latest recipe takes precedence over the previous ones.
+    #[serde(default)]
+    pub extends: Vec<String>,
+
     #[serde(default)]
     pub steps: HashMap<String, RecipeStepConfig>,
+
+    /// Parameters of the recipe
+    ///
+    /// Services set them with the `with` table of their recipe reference,
+    /// and scripts use them through `{{ params.<name> }}` placeholders.
+    #[serde(default)]
+    pub params: BTreeMap<String, RecipeParamConfig>,
+}
+
+/// Parameter of a recipe
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct RecipeParamConfig {
+    /// Value used when the service doesn't provide one
+    #[serde(default)]
+    pub default: Option<String>,
+
+    /// Whether services must provide a value for this parameter
+    ///
+    /// Optional parameters without a default value are empty.
+    #[serde(default)]
+    pub required: bool,
+
+    /// Description of the parameter
+    #[serde(default)]
+    pub description: Option<String>,
 }
 
 /// Step in a recipe config file
 ///
-/// This is similar to a `ServiceStepConfig` with the exception that
-/// `RecipeStepConfig` doesn't support `depends_on`.
-#[derive(Default, Deserialize)]
+/// This is similar to a `ServiceStepConfig`, with the exception that
+/// dependencies on the service using the recipe are written with the `self`
+/// placeholder, e.g. `build:self`.
+#[derive(Clone, Default, Deserialize)]
 pub struct RecipeStepConfig {
+    /// List of step:service pairs that this step depends on
+    ///
+    /// These are added to the dependencies declared by services.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+
     /// Shell script to run on a 'check'
     #[serde(default)]
     pub check: ScriptConfig,
@@ -21,6 +61,29 @@ pub struct RecipeStepConfig {
     /// Shell script to run on a 'run'
     #[serde(default)]
     pub run: ScriptConfig,
+
+    /// Container image for this step
+    ///
+    /// Services can override this with their own container image.
+    #[serde(default)]
+    pub container_image: Option<String>,
+
+    /// Paths produced by a successful run, relative to the service folder
+    ///
+    /// Services can override this with their own outputs.
+    #[serde(default)]
+    pub outputs: Vec<String>,
+
+    /// Environment variables for this step
+    ///
+    /// Services can override these with their own variables.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Dotenv file with environment variables for this step, relative to the
+    /// recipe folder
+    #[serde(default)]
+    pub env_file: Option<String>,
 }
 
 #[cfg(test)]
@@ -31,12 +94,16 @@ mod tests {
     fn default() {
         let config = RecipeConfig::default();
 
+        assert_eq!(config.extends.len(), 0);
         assert_eq!(config.steps.len(), 0);
+        assert_eq!(config.params.len(), 0);
     }
 
     #[test]
     fn deserialize() {
         let data = "
+            extends = [\"base\"]
+
             [steps.my-step]
             depends_on = [\"a\", \"b\", \"c\"]
             run = true
@@ -45,19 +112,57 @@ mod tests {
 
         let config: RecipeConfig = toml::from_str(data).expect("failed to deserialize data");
 
+        assert_eq!(config.extends, ["base"]);
         assert!(config.steps.contains_key("my-step"));
 
         let step = config.steps.get("my-step").expect("failed to get step");
+        assert_eq!(step.depends_on, ["a", "b", "c"]);
         assert_eq!(step.run, ScriptConfig::Boolean(true));
         assert_eq!(step.check, ScriptConfig::Boolean(true));
     }
 
+    #[test]
+    fn deserialize_params() {
+        let data = "
+            [params.dockerfile]
+            default = \"Dockerfile\"
+            description = \"Path to the Dockerfile\"
+
+            [params.registry]
+            required = true
+
+            [steps.build]
+            run = \"docker build -f {{ params.dockerfile }} .\"
+        ";
+
+        let config: RecipeConfig = toml::from_str(data).expect("failed to deserialize data");
+
+        let dockerfile = config
+            .params
+            .get("dockerfile")
+            .expect("failed to get param");
+        assert_eq!(dockerfile.default.as_deref(), Some("Dockerfile"));
+        assert!(!dockerfile.required);
+        assert_eq!(
+            dockerfile.description.as_deref(),
+            Some("Path to the Dockerfile")
+        );
+        let registry = config.params.get("registry").expect("failed to get param");
+        assert_eq!(registry.default, None);
+        assert!(registry.required);
+    }
+
     #[test]
     fn default_step() {
         let step_config = RecipeStepConfig::default();
 
+        assert_eq!(step_config.depends_on.len(), 0);
         assert_eq!(step_config.check, ScriptConfig::None);
         assert_eq!(step_config.run, ScriptConfig::None);
+        assert_eq!(step_config.container_image, None);
+        assert_eq!(step_config.outputs.len(), 0);
+        assert_eq!(step_config.env.len(), 0);
+        assert_eq!(step_config.env_file, None);
     }
 
     #[test]
@@ -65,10 +170,18 @@ mod tests {
         let data = "
             run = true
             check = true
+            container_image = \"my-image\"
+            outputs = [\"dist\", \"report.xml\"]
+            env = { TARGET = \"release\" }
+            env_file = \"build.env\"
         ";
         let step: RecipeStepConfig = toml::from_str(data).expect("unable to deserialize data");
 
         assert_eq!(step.run, ScriptConfig::Boolean(true));
         assert_eq!(step.check, ScriptConfig::Boolean(true));
+        assert_eq!(step.container_image.as_deref(), Some("my-image"));
+        assert_eq!(step.outputs, ["dist", "report.xml"]);
+        assert_eq!(step.env.get("TARGET").map(String::as_str), Some("release"));
+        assert_eq!(step.env_file.as_deref(), Some("build.env"));
     }
 }
@@ -3,7 +3,7 @@ use serde::Deserialize;
 /// Shell script to run
 ///
 /// This is used for both check and run actions within a service step.
-#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum ScriptConfig {
     /// Single string with multiple lines
@@ -36,6 +36,7 @@ impl ScriptConfig {
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for ScriptConfig {
     fn default() -> Self {
         Self::None
@@ -52,6 +53,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn deserialize_multiline() {
         let value = "this\nis\na\nvalue";
         let dh: DataHolder = toml::from_str(&format!("commands = \"\"\"{}\"\"\"", value))
@@ -66,6 +68,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn deserialize_array() {
         let value = "this\nis\na\nvalue".split("\n").collect::<Vec<_>>();
         let dh: DataHolder = toml::from_str(&format!("commands = [\"{}\"]", value.join("\",\"")))
@@ -80,6 +83,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn deserialize_boolean() {
         let value = true;
         let dh: DataHolder = toml::from_str("commands = true").expect("unable to read config");
@@ -1,6 +1,6 @@
 use super::ScriptConfig;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// Config file of a service
 #[derive(Default, Deserialize)]
@@ -10,8 +10,71 @@ pub struct ServiceConfig {
     pub steps: HashMap<String, ServiceStepConfig>,
 
     /// Array of recipes for this service
+    ///
+    /// Recipes are either referenced by name, or with arguments for their
+    /// parameters (e.g. `{ name = "docker", with = { dockerfile = "Dockerfile.prod" } }`).
     #[serde(default)]
-    pub recipes: Vec<String>,
+    pub recipes: Vec<RecipeRef>,
+
+    /// Additional inputs for this service outside of its folder
+    ///
+    /// These are glob patterns relative to the project root (e.g.
+    /// `lib/proto/**`). Changes to matching files mark the service as
+    /// changed.
+    #[serde(default)]
+    pub inputs: Vec<String>,
+
+    /// Container image for all steps of this service
+    ///
+    /// This overrides the container image from recipes and from the project
+    /// options.
+    #[serde(default)]
+    pub container_image: Option<String>,
+
+    /// Environment variables for all steps of this service
+    ///
+    /// These override the variables from recipes and from the project.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Dotenv file with environment variables for all steps of this service,
+    /// relative to the service folder
+    ///
+    /// Variables in `env` take precedence over the ones in this file.
+    #[serde(default)]
+    pub env_file: Option<String>,
+}
+
+/// Reference to a recipe in a service config file
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum RecipeRef {
+    /// Recipe without arguments
+    Name(String),
+    /// Recipe with arguments for its parameters
+    WithArgs {
+        name: String,
+        #[serde(default)]
+        with: BTreeMap<String, String>,
+    },
+}
+
+impl RecipeRef {
+    /// Name of the recipe
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Name(name) => name,
+            Self::WithArgs { name, .. } => name,
+        }
+    }
+
+    /// Arguments for the parameters of the recipe
+    pub fn args(&self) -> Option<&BTreeMap<String, String>> {
+        match self {
+            Self::Name(_) => None,
+            Self::WithArgs { with, .. } => Some(with),
+        }
+    }
 }
 
 /// Step in a service config file
@@ -29,6 +92,27 @@ pub struct ServiceStepConfig {
     /// Shell script to run on a 'run'
     #[serde(default)]
     pub run: ScriptConfig,
+
+    /// Container image for this step
+    #[serde(default)]
+    pub container_image: Option<String>,
+
+    /// Paths produced by a successful run, relative to the service folder
+    ///
+    /// These can be files or folders. They are collected after each
+    /// successful run and made available to the step:service pairs that
+    /// depend on this one.
+    #[serde(default)]
+    pub outputs: Vec<String>,
+
+    /// Environment variables for this step
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// Dotenv file with environment variables for this step, relative to the
+    /// service folder
+    #[serde(default)]
+    pub env_file: Option<String>,
 }
 
 #[cfg(test)]
@@ -41,28 +125,78 @@ mod tests {
 
         assert_eq!(config.steps.len(), 0);
         assert_eq!(config.recipes.len(), 0);
+        assert_eq!(config.inputs.len(), 0);
+        assert_eq!(config.container_image, None);
+        assert_eq!(config.env.len(), 0);
+        assert_eq!(config.env_file, None);
     }
 
     #[test]
     fn deserialize() {
         let data = "
             recipes = [\"my-recipe\"]
+            inputs = [\"lib/proto/**\"]
+            container_image = \"my-service-image\"
+            env_file = \".env\"
+
+            [env]
+            LOG_LEVEL = \"debug\"
 
             [steps.my-step]
             depends_on = [\"a\", \"b\", \"c\"]
             run = true
             check = true
+            container_image = \"my-step-image\"
+            outputs = [\"dist\"]
+            env = { TARGET = \"release\" }
+            env_file = \"build.env\"
         ";
 
         let config: ServiceConfig = toml::from_str(data).expect("failed to deserialize data");
 
-        assert_eq!(config.recipes, ["my-recipe"]);
+        assert_eq!(config.recipes, [RecipeRef::Name(String::from("my-recipe"))]);
+        assert_eq!(config.inputs, ["lib/proto/**"]);
+        assert_eq!(config.container_image.as_deref(), Some("my-service-image"));
         assert!(config.steps.contains_key("my-step"));
 
         let step = config.steps.get("my-step").expect("failed to get step");
         assert_eq!(step.depends_on, ["a", "b", "c"]);
         assert_eq!(step.run, ScriptConfig::Boolean(true));
         assert_eq!(step.check, ScriptConfig::Boolean(true));
+        assert_eq!(step.container_image.as_deref(), Some("my-step-image"));
+        assert_eq!(step.outputs, ["dist"]);
+        assert_eq!(step.env.get("TARGET").map(String::as_str), Some("release"));
+        assert_eq!(step.env_file.as_deref(), Some("build.env"));
+        assert_eq!(
+            config.env.get("LOG_LEVEL").map(String::as_str),
+            Some("debug")
+        );
+        assert_eq!(config.env_file.as_deref(), Some(".env"));
+    }
+
+    #[test]
+    fn deserialize_recipe_refs() {
+        let data = "
+            recipes = [
+                \"my-recipe\",
+                { name = \"docker\", with = { dockerfile = \"Dockerfile.prod\" } },
+                { name = \"no-args\" },
+            ]
+        ";
+
+        let config: ServiceConfig = toml::from_str(data).expect("failed to deserialize data");
+
+        let names: Vec<&str> = config.recipes.iter().map(RecipeRef::name).collect();
+        assert_eq!(names, ["my-recipe", "docker", "no-args"]);
+        assert_eq!(config.recipes[0].args(), None);
+        assert_eq!(
+            config.recipes[1]
+                .args()
+                .and_then(|args| args.get("dockerfile"))
+                .map(String::as_str),
+            Some("Dockerfile.prod")
+        );
+        assert_eq!(config.recipes[2].args().map(BTreeMap::len), Some(0));
     }
 
     #[test]
@@ -72,6 +206,10 @@ mod tests {
         assert_eq!(step.depends_on.len(), 0);
         assert_eq!(step.check, ScriptConfig::None);
         assert_eq!(step.run, ScriptConfig::None);
+        assert_eq!(step.container_image, None);
+        assert_eq!(step.outputs.len(), 0);
+        assert_eq!(step.env.len(), 0);
+        assert_eq!(step.env_file, None);
     }
 
     #[test]
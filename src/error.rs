@@ -18,17 +18,146 @@ pub enum Error {
         path: PathBuf,
         source: toml::de::Error,
     },
+    CannotReadEnvFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    InvalidEnvFile {
+        path: PathBuf,
+        line: String,
+    },
+    CannotReadServiceFolder {
+        path: PathBuf,
+        source: std::io::Error,
+    },
 
     // Project errors
+    ProjectNotFound {
+        path: PathBuf,
+    },
     ProjectIsNotGitRepo {
         path: PathBuf,
         source: git2::Error,
     },
+    UnknownStep {
+        name: String,
+        referenced_by: String,
+    },
+    UnknownService {
+        name: String,
+        referenced_by: String,
+    },
+    InvalidDependency {
+        name: String,
+        referenced_by: String,
+    },
+    DependencyCycle {
+        path: Vec<String>,
+    },
+    MissingStep {
+        name: String,
+    },
+    MissingService {
+        name: String,
+    },
+    ArtifactsDirOutsideProject {
+        path: String,
+    },
+    OutputVarCollision {
+        name: String,
+        other: String,
+    },
+
+    // Change detection errors
+    CannotResolveRevision {
+        rev: String,
+        source: git2::Error,
+    },
+    CannotDiff {
+        source: git2::Error,
+    },
+    CannotListTrackedFiles {
+        source: git2::Error,
+    },
 
     // Service errors
     MissingRecipes {
         names: Vec<String>,
     },
+    InvalidInputPattern {
+        service: String,
+        pattern: String,
+        source: glob::PatternError,
+    },
+    RecipeCycle {
+        path: Vec<String>,
+    },
+    MissingRecipeParam {
+        service: String,
+        recipe: String,
+        param: String,
+    },
+    UnknownRecipeParam {
+        service: String,
+        recipe: String,
+        param: String,
+    },
+
+    // Template errors
+    UndefinedVariable {
+        name: String,
+        variable: String,
+    },
+    UnclosedPlaceholder {
+        name: String,
+    },
+
+    // Execution errors
+    CannotExecuteScript {
+        name: String,
+        source: std::io::Error,
+    },
+
+    // Artifact errors
+    InvalidOutputPath {
+        name: String,
+        path: String,
+    },
+    MissingOutput {
+        name: String,
+        path: String,
+    },
+    CannotCopyArtifacts {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    CannotAccessValuesFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    InvalidOutputValue {
+        name: String,
+        line: String,
+    },
+
+    // Report errors
+    CannotWriteReport {
+        source: std::io::Error,
+    },
+
+    // Cache errors
+    CannotReadInputFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    CannotReadCache {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    CannotWriteCache {
+        path: PathBuf,
+        source: std::io::Error,
+    },
 }
 
 impl fmt::Display for Error {
@@ -50,17 +179,170 @@ impl fmt::Display for Error {
                 path.display(),
                 source
             ),
+            Self::CannotReadEnvFile { path, source } => write!(
+                f,
+                "cannot read env file '{}': {}",
+                path.display(),
+                source
+            ),
+            Self::InvalidEnvFile { path, line } => write!(
+                f,
+                "invalid line in env file '{}': expected 'KEY=value', got '{}'",
+                path.display(),
+                line
+            ),
+            Self::CannotReadServiceFolder { path, source } => write!(
+                f,
+                "cannot read service folder '{}': {}",
+                path.display(),
+                source
+            ),
             // Project errors
+            Self::ProjectNotFound { path } => {
+                write!(f, "no project found in '{}' or its parents", path.display())
+            }
             Self::ProjectIsNotGitRepo { path, source } => write!(
                 f,
                 "project is not a git repo at '{}': {}",
                 path.display(),
                 source
             ),
+            Self::UnknownStep {
+                name,
+                referenced_by,
+            } => write!(f, "unknown step '{}' in '{}'", name, referenced_by),
+            Self::UnknownService {
+                name,
+                referenced_by,
+            } => write!(f, "unknown service '{}' in '{}'", name, referenced_by),
+            Self::InvalidDependency {
+                name,
+                referenced_by,
+            } => write!(
+                f,
+                "invalid dependency '{}' in '{}': expected 'step:service'",
+                name, referenced_by
+            ),
+            Self::DependencyCycle { path } => {
+                write!(f, "dependency cycle detected: '{}'", path.join("' -> '"))
+            }
+            Self::MissingStep { name } => write!(f, "missing step: '{}'", name),
+            Self::MissingService { name } => write!(f, "missing service: '{}'", name),
+            Self::ArtifactsDirOutsideProject { path } => write!(
+                f,
+                "artifacts folder '{}' must be within the project",
+                path
+            ),
+            Self::OutputVarCollision { name, other } => write!(
+                f,
+                "outputs of '{}' and '{}' use the same environment variables",
+                name, other
+            ),
+            // Change detection errors
+            Self::CannotResolveRevision { rev, source } => {
+                write!(f, "cannot resolve revision '{}': {}", rev, source)
+            }
+            Self::CannotDiff { source } => write!(f, "cannot compute changes: {}", source),
+            Self::CannotListTrackedFiles { source } => {
+                write!(f, "cannot list tracked files: {}", source)
+            }
             // Service errors
             Self::MissingRecipes { names } => {
                 write!(f, "missing one or more recipes: '{}'", names.join(","))
             }
+            Self::InvalidInputPattern {
+                service,
+                pattern,
+                source,
+            } => write!(
+                f,
+                "invalid input pattern '{}' for service '{}': {}",
+                pattern, service, source
+            ),
+            Self::RecipeCycle { path } => {
+                write!(f, "recipe cycle detected: '{}'", path.join("' -> '"))
+            }
+            Self::MissingRecipeParam {
+                service,
+                recipe,
+                param,
+            } => write!(
+                f,
+                "missing value for required parameter '{}' of recipe '{}' in service '{}'",
+                param, recipe, service
+            ),
+            Self::UnknownRecipeParam {
+                service,
+                recipe,
+                param,
+            } => write!(
+                f,
+                "unknown parameter '{}' for recipe '{}' in service '{}'",
+                param, recipe, service
+            ),
+            // Template errors
+            Self::UndefinedVariable { name, variable } => write!(
+                f,
+                "undefined variable '{}' in script for '{}'",
+                variable, name
+            ),
+            Self::UnclosedPlaceholder { name } => write!(
+                f,
+                "unclosed placeholder in script for '{}': expected '}}}}'",
+                name
+            ),
+
+            // Execution errors
+            Self::CannotExecuteScript { name, source } => {
+                write!(f, "cannot execute script for '{}': {}", name, source)
+            }
+
+            // Artifact errors
+            Self::InvalidOutputPath { name, path } => write!(
+                f,
+                "invalid output '{}' for '{}': outputs must be relative paths within the service folder",
+                path, name
+            ),
+            Self::MissingOutput { name, path } => {
+                write!(f, "'{}' did not produce output '{}'", name, path)
+            }
+            Self::CannotCopyArtifacts { path, source } => write!(
+                f,
+                "cannot copy artifacts to '{}': {}",
+                path.display(),
+                source
+            ),
+            Self::CannotAccessValuesFile { path, source } => write!(
+                f,
+                "cannot access output values file '{}': {}",
+                path.display(),
+                source
+            ),
+            Self::InvalidOutputValue { name, line } => write!(
+                f,
+                "invalid output value from '{}': expected 'key=value', got '{}'",
+                name, line
+            ),
+
+            // Report errors
+            Self::CannotWriteReport { source } => write!(f, "cannot write report: {}", source),
+
+            // Cache errors
+            Self::CannotReadInputFile { path, source } => {
+                write!(f, "cannot read input file '{}': {}", path.display(), source)
+            }
+            Self::CannotReadCache { path, source } => write!(
+                f,
+                "cannot read cache entry '{}': {}",
+                path.display(),
+                source
+            ),
+            Self::CannotWriteCache { path, source } => write!(
+                f,
+                "cannot write cache entry '{}': {}",
+                path.display(),
+                source
+            ),
         }
     }
 }
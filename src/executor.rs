@@ -0,0 +1,678 @@
+use crate::report::OutputStream;
+use crate::{project::SERVICE_FOLDER, Error, Result, Script, ServiceStep};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_SHELL: [&str; 3] = ["sh", "-e", "-c"];
+const DEFAULT_CONTAINER_BINARY: &str = "docker";
+/// Folder where the project is mounted within containers
+const CONTAINER_ROOT: &str = "/orcs";
+
+/// Result of a script execution
+#[derive(Clone, Debug)]
+pub struct ScriptOutput {
+    /// Exit code of the script
+    ///
+    /// This is `None` if the process was terminated by a signal.
+    pub code: Option<i32>,
+
+    /// Captured standard output
+    pub stdout: Vec<u8>,
+
+    /// Captured standard error
+    pub stderr: Vec<u8>,
+
+    /// Time it took to run the script
+    pub duration: Duration,
+}
+
+impl ScriptOutput {
+    /// Whether the script exited successfully
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// Backend that can execute the scripts of a `ServiceStep`
+///
+/// Executors are shared between worker threads when running step:service
+/// pairs concurrently.
+pub trait Executor: Sync {
+    /// Execute a script in the context of a `ServiceStep`
+    ///
+    /// The script receives the variables in `env` on top of the executor's
+    /// own environment.
+    fn execute(
+        &self,
+        step: &ServiceStep,
+        script: &str,
+        env: &BTreeMap<String, String>,
+    ) -> Result<ScriptOutput> {
+        self.execute_with_output(step, script, env, &mut |_, _| Ok(()))
+    }
+
+    /// Execute a script and pass its output to `on_output` as it arrives
+    ///
+    /// `on_output` is called once per line written by the script. The
+    /// output is also captured in the returned `ScriptOutput`. If
+    /// `on_output` returns an error, the script still runs to completion
+    /// and that error is returned.
+    fn execute_with_output(
+        &self,
+        step: &ServiceStep,
+        script: &str,
+        env: &BTreeMap<String, String>,
+        on_output: &mut dyn FnMut(OutputStream, &[u8]) -> Result<()>,
+    ) -> Result<ScriptOutput>;
+
+    /// Path of a file within the project, as seen by scripts
+    fn script_path(&self, path: &Path) -> PathBuf;
+
+    /// Execute a resolved `Script`
+    ///
+    /// Only `Script::Script` values result in an execution. Overrides and
+    /// empty scripts have nothing to execute and return `None`.
+    fn execute_script(
+        &self,
+        step: &ServiceStep,
+        script: &Script,
+        env: &BTreeMap<String, String>,
+    ) -> Result<Option<ScriptOutput>> {
+        match script {
+            Script::Script(body) => self.execute(step, body, env).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Executor running scripts on the local machine
+///
+/// Scripts are passed as the last argument to the shell command, with the
+/// service folder as working directory.
+pub struct LocalExecutor {
+    /// Root folder of the project
+    root: PathBuf,
+
+    /// Shell command and its arguments
+    shell: Vec<String>,
+}
+
+impl LocalExecutor {
+    /// Create a new executor for the project at `root`
+    ///
+    /// By default, scripts are run with `sh -e -c`.
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            root: root.into(),
+            shell: DEFAULT_SHELL.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    /// Use a different shell command to run scripts
+    ///
+    /// The first value is the program to run and the remaining values are
+    /// passed as arguments before the script.
+    pub fn with_shell<I, S>(mut self, shell: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.shell = shell.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Working directory for a given `ServiceStep`
+    fn workdir(&self, step: &ServiceStep) -> PathBuf {
+        service_path(&self.root, &step.service_name)
+    }
+}
+
+impl Executor for LocalExecutor {
+    fn execute_with_output(
+        &self,
+        step: &ServiceStep,
+        script: &str,
+        env: &BTreeMap<String, String>,
+        on_output: &mut dyn FnMut(OutputStream, &[u8]) -> Result<()>,
+    ) -> Result<ScriptOutput> {
+        let (program, args) = match self.shell.split_first() {
+            Some(shell) => shell,
+            None => {
+                return Err(Error::CannotExecuteScript {
+                    name: step.name.clone(),
+                    source: std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "empty shell command",
+                    ),
+                })
+            }
+        };
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .arg(script)
+            .envs(env)
+            .current_dir(self.workdir(step));
+
+        run_command(step, &mut command, on_output)
+    }
+
+    fn script_path(&self, path: &Path) -> PathBuf {
+        path.to_path_buf()
+    }
+}
+
+/// Executor running scripts inside containers
+///
+/// This uses a docker-compatible command line, such as `docker` or `podman`.
+/// The project root is mounted in the container and the service folder is
+/// used as working directory.
+///
+/// Scripts run in the container image of their `ServiceStep` if it has one,
+/// or the executor's image otherwise.
+pub struct ContainerExecutor {
+    /// Root folder of the project
+    root: PathBuf,
+
+    /// Container image to run scripts in
+    image: String,
+
+    /// Path to the container command line
+    binary: PathBuf,
+
+    /// Shell command and its arguments within the container
+    shell: Vec<String>,
+}
+
+impl ContainerExecutor {
+    /// Create a new executor for the project at `root`
+    ///
+    /// By default, this uses the `docker` command and runs scripts with
+    /// `sh -e -c`.
+    pub fn new<P, S>(root: P, image: S) -> Self
+    where
+        P: Into<PathBuf>,
+        S: Into<String>,
+    {
+        Self {
+            root: root.into(),
+            image: image.into(),
+            binary: PathBuf::from(DEFAULT_CONTAINER_BINARY),
+            shell: DEFAULT_SHELL.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    /// Use a different container command line (e.g. `podman`)
+    pub fn with_binary<P>(mut self, binary: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.binary = binary.into();
+        self
+    }
+
+    /// Use a different shell command to run scripts within the container
+    pub fn with_shell<I, S>(mut self, shell: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.shell = shell.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Working directory for a given `ServiceStep` within the container
+    fn workdir(&self, step: &ServiceStep) -> String {
+        format!(
+            "{}/{}/{}",
+            CONTAINER_ROOT, SERVICE_FOLDER, step.service_name
+        )
+    }
+}
+
+impl Executor for ContainerExecutor {
+    fn execute_with_output(
+        &self,
+        step: &ServiceStep,
+        script: &str,
+        env: &BTreeMap<String, String>,
+        on_output: &mut dyn FnMut(OutputStream, &[u8]) -> Result<()>,
+    ) -> Result<ScriptOutput> {
+        let mut command = Command::new(&self.binary);
+        command
+            .arg("run")
+            .arg("--rm")
+            .arg("-v")
+            .arg(format!("{}:{}", self.root.display(), CONTAINER_ROOT))
+            .arg("-w")
+            .arg(self.workdir(step));
+        // Only pass variable names on the command line, the container command
+        // line reads the values from its own environment.
+        for (key, value) in env {
+            command.arg("-e").arg(key).env(key, value);
+        }
+        command
+            .arg(step.container_image().unwrap_or(&self.image))
+            .args(&self.shell)
+            .arg(script)
+            .current_dir(&self.root);
+
+        run_command(step, &mut command, on_output)
+    }
+
+    /// Paths within the project root are translated to the folder where the
+    /// project is mounted. Other paths are not available in containers and
+    /// are returned as-is.
+    fn script_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => Path::new(CONTAINER_ROOT).join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+}
+
+/// Run a command for a `ServiceStep` and capture its output
+///
+/// Standard output and standard error are read concurrently, so that lines
+/// are passed to `on_output` in the order the command writes them.
+fn run_command(
+    step: &ServiceStep,
+    command: &mut Command,
+    on_output: &mut dyn FnMut(OutputStream, &[u8]) -> Result<()>,
+) -> Result<ScriptOutput> {
+    let error = |source| Error::CannotExecuteScript {
+        name: step.name.clone(),
+        source,
+    };
+
+    let start = Instant::now();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(error)?;
+    let stdout = child.stdout.take().expect("failed to capture stdout");
+    let stderr = child.stderr.take().expect("failed to capture stderr");
+
+    let mut output = ScriptOutput {
+        code: None,
+        stdout: Default::default(),
+        stderr: Default::default(),
+        duration: Default::default(),
+    };
+    let mut on_output_result = Ok(());
+    let (sender, receiver) = mpsc::channel();
+    let read_result = thread::scope(|scope| {
+        let readers = vec![
+            scope.spawn({
+                let sender = sender.clone();
+                move || read_lines(OutputStream::Stdout, stdout, sender)
+            }),
+            scope.spawn(move || read_lines(OutputStream::Stderr, stderr, sender)),
+        ];
+
+        // This stops once both readers reached the end of their stream
+        for (stream, line) in receiver {
+            match stream {
+                OutputStream::Stdout => output.stdout.extend(&line),
+                OutputStream::Stderr => output.stderr.extend(&line),
+            }
+            if on_output_result.is_ok() {
+                on_output_result = on_output(stream, &line);
+            }
+        }
+
+        readers
+            .into_iter()
+            .try_for_each(|reader| reader.join().expect("failed to join output reader"))
+    });
+
+    let status = child.wait().map_err(error)?;
+    read_result.map_err(error)?;
+    on_output_result?;
+
+    output.code = status.code();
+    output.duration = start.elapsed();
+    Ok(output)
+}
+
+/// Send the lines of an output stream of a command
+fn read_lines<R>(
+    stream: OutputStream,
+    reader: R,
+    sender: Sender<(OutputStream, Vec<u8>)>,
+) -> std::io::Result<()>
+where
+    R: Read,
+{
+    let mut reader = BufReader::new(reader);
+    loop {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        // The receiver is only dropped once all readers are done
+        let _ = sender.send((stream, line));
+    }
+}
+
+/// Folder of a service within a project
+pub(crate) fn service_path<P>(root: P, service_name: &str) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    root.as_ref().join(SERVICE_FOLDER).join(service_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServiceStepConfig;
+    use crate::test_utils::create_step;
+    use tempfile::tempdir;
+
+    #[test]
+    fn execute() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step = create_step(
+            project_dir.path(),
+            "my-service",
+            "my-step",
+            &Default::default(),
+        );
+        let executor = LocalExecutor::new(project_dir.path());
+
+        let output = executor
+            .execute(&step, "echo out\necho err >&2\nexit 3", &Default::default())
+            .expect("failed to execute script");
+
+        assert_eq!(output.code, Some(3));
+        assert!(!output.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+    }
+
+    #[test]
+    fn execute_workdir() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step = create_step(
+            project_dir.path(),
+            "my-service",
+            "my-step",
+            &Default::default(),
+        );
+        let executor = LocalExecutor::new(project_dir.path());
+
+        let output = executor
+            .execute(&step, "pwd -P", &Default::default())
+            .expect("failed to execute script");
+
+        let workdir = service_path(project_dir.path(), "my-service")
+            .canonicalize()
+            .expect("failed to canonicalize service folder");
+        assert!(output.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            workdir.to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn execute_env() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step = create_step(
+            project_dir.path(),
+            "my-service",
+            "my-step",
+            &Default::default(),
+        );
+        let executor = LocalExecutor::new(project_dir.path());
+        let env: BTreeMap<String, String> =
+            vec![(String::from("MY_VAR"), String::from("my value"))]
+                .into_iter()
+                .collect();
+
+        let output = executor
+            .execute(&step, "echo \"$MY_VAR\"", &env)
+            .expect("failed to execute script");
+
+        assert!(output.success());
+        assert_eq!(output.stdout, b"my value\n");
+    }
+
+    #[test]
+    fn execute_with_output() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step = create_step(
+            project_dir.path(),
+            "my-service",
+            "my-step",
+            &Default::default(),
+        );
+        let executor = LocalExecutor::new(project_dir.path());
+        let go = project_dir.path().join("go");
+
+        // The script waits for the first line to be received before going on,
+        // which can only succeed if the output is passed as it arrives.
+        let mut lines: Vec<(OutputStream, Vec<u8>)> = Default::default();
+        let output = executor
+            .execute_with_output(
+                &step,
+                "echo ready\nfor i in $(seq 50); do\n  [ -f ../../go ] && echo done >&2 && exit 0\n  sleep 0.1\ndone\nexit 1",
+                &Default::default(),
+                &mut |stream, data| {
+                    if data == b"ready\n" {
+                        std::fs::write(&go, "").expect("failed to write file");
+                    }
+                    lines.push((stream, data.to_vec()));
+                    Ok(())
+                },
+            )
+            .expect("failed to execute script");
+
+        assert!(output.success());
+        assert_eq!(
+            lines,
+            [
+                (OutputStream::Stdout, b"ready\n".to_vec()),
+                (OutputStream::Stderr, b"done\n".to_vec())
+            ]
+        );
+        assert_eq!(output.stdout, b"ready\n");
+        assert_eq!(output.stderr, b"done\n");
+    }
+
+    #[test]
+    fn execute_with_shell() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step = create_step(
+            project_dir.path(),
+            "my-service",
+            "my-step",
+            &Default::default(),
+        );
+        let executor = LocalExecutor::new(project_dir.path()).with_shell(vec!["sh", "-c"]);
+
+        // Without '-e', the script keeps going after a failed command
+        let output = executor
+            .execute(&step, "false\necho done", &Default::default())
+            .expect("failed to execute script");
+
+        assert!(output.success());
+        assert_eq!(output.stdout, b"done\n");
+    }
+
+    /// Create a stub container command line that prints its arguments
+    fn create_container_stub<P>(root: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = root.as_ref().join("container-stub");
+        std::fs::write(
+            &path,
+            "#!/bin/sh\nfor arg in \"$@\"; do echo \"$arg\"; done\nexit 4\n",
+        )
+        .expect("failed to write container stub");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .expect("failed to set container stub permissions");
+
+        path
+    }
+
+    #[test]
+    fn container_execute() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step = create_step(
+            project_dir.path(),
+            "my-service",
+            "my-step",
+            &Default::default(),
+        );
+        let stub = create_container_stub(project_dir.path());
+        let executor =
+            ContainerExecutor::new(project_dir.path(), "my-image:latest").with_binary(&stub);
+
+        let output = executor
+            .execute(&step, "echo hello", &Default::default())
+            .expect("failed to execute script");
+
+        let args = String::from_utf8_lossy(&output.stdout);
+        let mount = format!("{}:/orcs", project_dir.path().display());
+        assert_eq!(
+            args.lines().collect::<Vec<_>>(),
+            [
+                "run",
+                "--rm",
+                "-v",
+                &mount,
+                "-w",
+                "/orcs/srv/my-service",
+                "my-image:latest",
+                "sh",
+                "-e",
+                "-c",
+                "echo hello"
+            ]
+        );
+        assert_eq!(output.code, Some(4));
+    }
+
+    #[test]
+    fn container_execute_step_image() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step_config = ServiceStepConfig {
+            container_image: Some(String::from("my-step-image")),
+            ..Default::default()
+        };
+        let step = create_step(project_dir.path(), "my-service", "my-step", &step_config);
+        let stub = create_container_stub(project_dir.path());
+        let executor =
+            ContainerExecutor::new(project_dir.path(), "my-image:latest").with_binary(&stub);
+
+        let output = executor
+            .execute(&step, "echo hello", &Default::default())
+            .expect("failed to execute script");
+
+        let args = String::from_utf8_lossy(&output.stdout);
+        assert!(args.lines().any(|arg| arg == "my-step-image"));
+        assert!(!args.lines().any(|arg| arg == "my-image:latest"));
+    }
+
+    #[test]
+    fn container_execute_env() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step = create_step(
+            project_dir.path(),
+            "my-service",
+            "my-step",
+            &Default::default(),
+        );
+        let stub = create_container_stub(project_dir.path());
+        let executor =
+            ContainerExecutor::new(project_dir.path(), "my-image:latest").with_binary(&stub);
+        let env: BTreeMap<String, String> =
+            vec![(String::from("MY_VAR"), String::from("my value"))]
+                .into_iter()
+                .collect();
+
+        let output = executor
+            .execute(&step, "echo hello", &env)
+            .expect("failed to execute script");
+
+        let args = String::from_utf8_lossy(&output.stdout);
+        let args: Vec<&str> = args.lines().collect();
+        assert_eq!(args[6..9], ["-e", "MY_VAR", "my-image:latest"]);
+        assert!(!args.contains(&"my value"));
+    }
+
+    #[test]
+    fn container_script_path() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let executor = ContainerExecutor::new(project_dir.path(), "my-image:latest");
+
+        assert_eq!(
+            executor.script_path(&project_dir.path().join(".orcs/artifacts")),
+            Path::new("/orcs/.orcs/artifacts")
+        );
+        assert_eq!(
+            executor.script_path(Path::new("/elsewhere")),
+            Path::new("/elsewhere")
+        );
+    }
+
+    #[test]
+    fn container_execute_missing_binary() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step = create_step(
+            project_dir.path(),
+            "my-service",
+            "my-step",
+            &Default::default(),
+        );
+        let executor = ContainerExecutor::new(project_dir.path(), "my-image:latest")
+            .with_binary(project_dir.path().join("does-not-exist"));
+
+        match executor.execute(&step, "echo hello", &Default::default()) {
+            Err(Error::CannotExecuteScript { name, .. }) => assert_eq!(name, "my-step:my-service"),
+            _ => panic!("expected a script execution error"),
+        }
+    }
+
+    #[test]
+    fn execute_script() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let step = create_step(
+            project_dir.path(),
+            "my-service",
+            "my-step",
+            &Default::default(),
+        );
+        let executor = LocalExecutor::new(project_dir.path());
+
+        let output = executor
+            .execute_script(&step, &Script::Override(true), &Default::default())
+            .expect("failed to execute script");
+        assert!(output.is_none());
+
+        let output = executor
+            .execute_script(
+                &step,
+                &Script::Script(String::from("true")),
+                &Default::default(),
+            )
+            .expect("failed to execute script");
+        assert!(output.expect("missing script output").success());
+    }
+}
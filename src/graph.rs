@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Directed graph of dependencies between named nodes
+///
+/// Nodes are kept in sorted maps so that traversals, and therefore error
+/// messages, are deterministic.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    /// Dependencies of each node
+    edges: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Visit state for the depth-first search in `Graph::find_cycle`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+impl Graph {
+    /// Add a node to the graph if it doesn't exist yet
+    pub fn add_node(&mut self, node: &str) {
+        if !self.edges.contains_key(node) {
+            self.edges.insert(node.to_string(), BTreeSet::new());
+        }
+    }
+
+    /// Add a dependency from `node` to `dependency`
+    ///
+    /// Both nodes are added to the graph if they don't exist yet.
+    pub fn add_edge(&mut self, node: &str, dependency: &str) {
+        self.add_node(dependency);
+        self.add_node(node);
+        self.edges
+            .get_mut(node)
+            .expect("failed to get node")
+            .insert(dependency.to_string());
+    }
+
+    /// Check if the graph contains a node
+    pub fn contains(&self, node: &str) -> bool {
+        self.edges.contains_key(node)
+    }
+
+    /// Direct dependencies of a node
+    pub fn dependencies(&self, node: &str) -> impl Iterator<Item = &String> {
+        self.edges.get(node).into_iter().flatten()
+    }
+
+    /// Dependencies of a node for which `filter` returns `true`
+    ///
+    /// Dependencies for which `filter` returns `false` are replaced by their
+    /// own dependencies, recursively.
+    pub fn filtered_dependencies<F>(&self, node: &str, filter: F) -> BTreeSet<&String>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut dependencies: BTreeSet<&String> = Default::default();
+        let mut visited: BTreeSet<&str> = Default::default();
+        let mut queue: Vec<&String> = self.dependencies(node).collect();
+
+        while let Some(dependency) = queue.pop() {
+            if !visited.insert(dependency) {
+                continue;
+            }
+            if filter(dependency) {
+                dependencies.insert(dependency);
+            } else {
+                queue.extend(self.dependencies(dependency));
+            }
+        }
+
+        dependencies
+    }
+
+    /// Group the given nodes and all their dependencies in layers
+    ///
+    /// Nodes in a layer only depend on nodes from previous layers. Nodes for
+    /// which `filter` returns `false` are left out, but their dependencies are
+    /// still part of the layers. This assumes that the graph is acyclic.
+    pub fn layers<'a, I, F>(&self, nodes: I, filter: F) -> Vec<Vec<String>>
+    where
+        I: IntoIterator<Item = &'a str>,
+        F: Fn(&str) -> bool,
+    {
+        let mut depths: BTreeMap<&str, usize> = Default::default();
+        for node in nodes {
+            self.depth(node, &filter, &mut depths);
+        }
+
+        let mut layers: Vec<Vec<String>> = Default::default();
+        for (node, depth) in depths {
+            if !filter(node) {
+                continue;
+            }
+            if layers.len() <= depth {
+                layers.resize_with(depth + 1, Default::default);
+            }
+            layers[depth].push(node.to_string());
+        }
+
+        layers
+    }
+
+    /// Length of the longest dependency chain below a node
+    ///
+    /// Only nodes for which `filter` returns `true` count towards the length.
+    fn depth<'a, F>(
+        &'a self,
+        node: &'a str,
+        filter: &F,
+        depths: &mut BTreeMap<&'a str, usize>,
+    ) -> usize
+    where
+        F: Fn(&str) -> bool,
+    {
+        if let Some(depth) = depths.get(node) {
+            return *depth;
+        }
+
+        let depth = self
+            .dependencies(node)
+            .map(|dependency| {
+                let depth = self.depth(dependency, filter, depths);
+                if filter(dependency) {
+                    depth + 1
+                } else {
+                    depth
+                }
+            })
+            .max()
+            .unwrap_or(0);
+        depths.insert(node, depth);
+
+        depth
+    }
+
+    /// Find a cycle in the graph
+    ///
+    /// If there is a cycle, this returns the path of the cycle, starting and
+    /// ending with the same node (e.g. `["a", "b", "a"]`).
+    pub fn find_cycle(&self) -> Option<Vec<String>> {
+        let mut visits: BTreeMap<&str, Visit> = Default::default();
+        let mut path: Vec<&str> = Default::default();
+
+        for node in self.edges.keys() {
+            if let Some(cycle) = self.visit(node, &mut visits, &mut path) {
+                return Some(cycle);
+            }
+        }
+
+        None
+    }
+
+    /// Depth-first visit used by `find_cycle`
+    fn visit<'a>(
+        &'a self,
+        node: &'a str,
+        visits: &mut BTreeMap<&'a str, Visit>,
+        path: &mut Vec<&'a str>,
+    ) -> Option<Vec<String>> {
+        match visits.get(node) {
+            Some(Visit::Done) => return None,
+            Some(Visit::InProgress) => {
+                // We came back to a node on the current path, so everything
+                // from that node onwards is part of the cycle.
+                let start = path
+                    .iter()
+                    .position(|n| *n == node)
+                    .expect("failed to find node in path");
+                let mut cycle: Vec<String> = path[start..].iter().map(|n| n.to_string()).collect();
+                cycle.push(node.to_string());
+                return Some(cycle);
+            }
+            None => (),
+        }
+
+        visits.insert(node, Visit::InProgress);
+        path.push(node);
+        for dependency in self.dependencies(node) {
+            if let Some(cycle) = self.visit(dependency, visits, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        visits.insert(node, Visit::Done);
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_edge() {
+        let mut graph = Graph::default();
+        graph.add_edge("a", "b");
+
+        assert!(graph.contains("a"));
+        assert!(graph.contains("b"));
+        assert_eq!(graph.dependencies("a").collect::<Vec<_>>(), ["b"]);
+        assert_eq!(graph.dependencies("b").count(), 0);
+    }
+
+    #[test]
+    fn layers() {
+        let mut graph = Graph::default();
+        graph.add_edge("c", "b");
+        graph.add_edge("c", "a");
+        graph.add_edge("b", "a");
+        graph.add_edge("d", "a");
+        graph.add_edge("e", "d");
+
+        assert_eq!(
+            graph.layers(vec!["c"], |_| true),
+            vec![vec!["a"], vec!["b"], vec!["c"]]
+        );
+        assert_eq!(
+            graph.layers(vec!["c", "d"], |_| true),
+            vec![vec!["a"], vec!["b", "d"], vec!["c"]]
+        );
+        assert_eq!(
+            graph.layers(vec![], |_| true),
+            Vec::new() as Vec<Vec<String>>
+        );
+    }
+
+    #[test]
+    fn layers_filter() {
+        let mut graph = Graph::default();
+        graph.add_edge("c", "b");
+        graph.add_edge("b", "a");
+        graph.add_edge("e", "d");
+        graph.add_edge("d", "a");
+
+        // Dependencies of 'd' are kept
+        assert_eq!(
+            graph.layers(vec!["c", "e"], |node| node != "d"),
+            vec![vec!["a"], vec!["b", "e"], vec!["c"]]
+        );
+        assert_eq!(
+            graph.layers(vec!["c", "e"], |node| node != "b" && node != "e"),
+            vec![vec!["a"], vec!["c", "d"]]
+        );
+    }
+
+    #[test]
+    fn filtered_dependencies() {
+        let mut graph = Graph::default();
+        graph.add_edge("c", "b");
+        graph.add_edge("c", "d");
+        graph.add_edge("b", "a");
+        graph.add_edge("d", "a");
+
+        assert_eq!(
+            graph
+                .filtered_dependencies("c", |node| node != "b")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["a", "d"]
+        );
+        assert_eq!(
+            graph
+                .filtered_dependencies("c", |node| node != "b" && node != "d")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["a"]
+        );
+    }
+
+    #[test]
+    fn find_cycle_acyclic() {
+        let mut graph = Graph::default();
+        graph.add_edge("a", "b");
+        graph.add_edge("a", "c");
+        graph.add_edge("b", "c");
+        graph.add_node("d");
+
+        assert_eq!(graph.find_cycle(), None);
+    }
+
+    #[test]
+    fn find_cycle() {
+        let mut graph = Graph::default();
+        graph.add_edge("a", "b");
+        graph.add_edge("b", "c");
+        graph.add_edge("c", "d");
+        graph.add_edge("d", "b");
+
+        assert_eq!(
+            graph.find_cycle().expect("failed to find cycle"),
+            ["b", "c", "d", "b"]
+        );
+    }
+
+    #[test]
+    fn find_cycle_self() {
+        let mut graph = Graph::default();
+        graph.add_edge("a", "a");
+
+        assert_eq!(
+            graph.find_cycle().expect("failed to find cycle"),
+            ["a", "a"]
+        );
+    }
+}
@@ -1,11 +1,30 @@
-mod config;
+mod artifacts;
+mod cache;
+mod changes;
+pub mod config;
 mod error;
+mod executor;
+mod graph;
+mod plan;
 mod project;
+pub mod report;
+mod runner;
+mod scheduler;
 mod service;
+mod template;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 
 pub use {
+    artifacts::{ArtifactStore, ARTIFACTS_DIR_VAR},
+    cache::{CacheBackend, CacheEntry, LocalCache, SharedCache},
+    changes::{DiffBase, DiffTarget},
     error::{Error, Result},
+    executor::{ContainerExecutor, Executor, LocalExecutor, ScriptOutput},
+    plan::Plan,
     project::Project,
-    service::Service,
+    runner::{CheckStatus, Runner, StepResult, StepStatus},
+    scheduler::Scheduler,
+    service::{Script, Service, ServiceStep, Source, StepSources},
 };
@@ -0,0 +1,416 @@
+use clap::{Args, Parser, Subcommand};
+use orcs::report::{JUnitReporter, JsonLinesReporter};
+use orcs::{
+    ArtifactStore, CheckStatus, ContainerExecutor, DiffBase, DiffTarget, Error, Executor,
+    LocalCache, LocalExecutor, Project, Result, Runner, Scheduler, SharedCache, StepResult,
+    StepStatus,
+};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Microservices monorepo orchestration tool
+#[derive(Parser)]
+#[command(name = "orcs", version)]
+struct Cli {
+    /// Path within the project, instead of the current directory
+    #[arg(short = 'C', long, global = true)]
+    project_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List all services
+    List,
+    /// Run a step on services, including its dependencies
+    Run(RunArgs),
+    /// Run the check scripts for a step on services
+    Check(CheckArgs),
+    /// List services with changes
+    Changed(ChangesArgs),
+    /// Show the dependency graph for a step, in the Graphviz DOT format
+    Graph(PlanArgs),
+    /// Show the resolved configuration of a service and where each value
+    /// comes from
+    Show(ShowArgs),
+    /// Validate the project configuration
+    Validate,
+}
+
+#[derive(Args)]
+struct PlanArgs {
+    /// Name of the step
+    step: String,
+
+    /// Services to use, instead of all services
+    services: Vec<String>,
+
+    #[command(flatten)]
+    changes: ChangesArgs,
+}
+
+#[derive(Args)]
+struct ShowArgs {
+    /// Name of the service
+    service: String,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[command(flatten)]
+    plan: PlanArgs,
+
+    #[command(flatten)]
+    executor: ExecutorArgs,
+
+    /// Maximum number of step:service pairs to run concurrently
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Write run events as JSON Lines to this file, or '-' for stdout
+    #[arg(long, value_name = "FILE")]
+    events: Option<PathBuf>,
+
+    /// Write a JUnit XML report to this file
+    #[arg(long, value_name = "FILE")]
+    junit: Option<PathBuf>,
+
+    /// Run all step:service pairs, ignoring results from previous runs
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Folder shared between machines to cache results in, instead of the
+    /// project `shared_cache_dir` option
+    #[arg(long, value_name = "DIR")]
+    shared_cache: Option<PathBuf>,
+}
+
+impl RunArgs {
+    /// Whether events are written to stdout instead of the human output
+    fn events_to_stdout(&self) -> bool {
+        self.events.as_deref() == Some(Path::new("-"))
+    }
+
+    /// Create the JSON Lines reporter, if requested
+    fn events_reporter(&self) -> Result<Option<JsonLinesReporter<Box<dyn Write + Send>>>> {
+        let path = match &self.events {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let writer: Box<dyn Write + Send> = if self.events_to_stdout() {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(path).map_err(|source| Error::CannotWriteReport { source })?)
+        };
+        Ok(Some(JsonLinesReporter::new(writer)))
+    }
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    #[command(flatten)]
+    plan: PlanArgs,
+
+    #[command(flatten)]
+    executor: ExecutorArgs,
+}
+
+#[derive(Args)]
+struct ExecutorArgs {
+    /// Run scripts in containers using the project container image
+    #[arg(long)]
+    container: bool,
+
+    /// Container command line to use with '--container'
+    #[arg(long, default_value = "docker")]
+    container_binary: PathBuf,
+}
+
+impl ExecutorArgs {
+    /// Create the executor for a project
+    fn executor(&self, project: &Project) -> Box<dyn Executor> {
+        if self.container {
+            Box::new(
+                ContainerExecutor::new(project.path(), &project.config().options.container_image)
+                    .with_binary(&self.container_binary),
+            )
+        } else {
+            Box::new(LocalExecutor::new(project.path()))
+        }
+    }
+}
+
+#[derive(Args)]
+struct ChangesArgs {
+    /// Only use services with changes since this revision
+    #[arg(long, conflicts_with = "merge_base")]
+    since: Option<String>,
+
+    /// Only use services with changes since the merge base with this revision
+    #[arg(long)]
+    merge_base: Option<String>,
+
+    /// Look for changes up to this revision, instead of the working tree
+    #[arg(long)]
+    target: Option<String>,
+}
+
+impl ChangesArgs {
+    /// Base revision for change detection, if any
+    fn base(&self) -> Option<DiffBase> {
+        match (&self.since, &self.merge_base) {
+            (Some(rev), _) => Some(DiffBase::Rev(rev.clone())),
+            (_, Some(rev)) => Some(DiffBase::MergeBase(rev.clone())),
+            _ => None,
+        }
+    }
+
+    /// Target for change detection
+    fn target(&self) -> DiffTarget {
+        match &self.target {
+            Some(rev) => DiffTarget::Rev(rev.clone()),
+            None => DiffTarget::WorkTree,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // Resolve the folder to start looking for the project from
+    let project_dir = cli
+        .project_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."));
+    let project_dir = match project_dir.canonicalize() {
+        Ok(project_dir) => project_dir,
+        Err(err) => {
+            eprintln!("error: cannot open '{}': {}", project_dir.display(), err);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(cli.command, project_dir) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Run a command and return whether it succeeded
+fn run(command: Command, project_dir: PathBuf) -> Result<bool> {
+    let project = Project::discover(project_dir)?;
+
+    match command {
+        Command::List => {
+            for service_name in all_services(&project)? {
+                println!("{}", service_name);
+            }
+            Ok(true)
+        }
+        Command::Run(args) => run_step(&project, &args),
+        Command::Check(args) => check_step(&project, &args),
+        Command::Changed(args) => {
+            let base = args
+                .base()
+                .unwrap_or_else(|| DiffBase::Rev(String::from("HEAD")));
+            for service_name in project.changed_services(&base, &args.target())? {
+                println!("{}", service_name);
+            }
+            Ok(true)
+        }
+        Command::Graph(args) => {
+            let services = select_services(&project, &args)?;
+            print!("{}", project.plan(&args.step, &services)?.to_dot());
+            Ok(true)
+        }
+        Command::Show(args) => {
+            let service = project.get_service(&args.service)?;
+            // Scripts that cannot be rendered are shown as written
+            let (service, errors) = service.render(|step| project.template_vars(step));
+            print!("{}", service);
+            for err in &errors {
+                eprintln!("error: {}", err);
+            }
+            Ok(errors.is_empty())
+        }
+        Command::Validate => {
+            project.validate()?;
+            println!("project '{}' is valid", project.config().name);
+            Ok(true)
+        }
+    }
+}
+
+/// Names of all services in the project, sorted
+fn all_services(project: &Project) -> Result<Vec<String>> {
+    Ok(project.service_names()?.into_iter().collect())
+}
+
+/// Services selected through the command line arguments
+fn select_services(project: &Project, args: &PlanArgs) -> Result<Vec<String>> {
+    let mut services = if args.services.is_empty() {
+        all_services(project)?
+    } else {
+        args.services.clone()
+    };
+
+    if let Some(base) = args.changes.base() {
+        let changed = project.changed_services(&base, &args.changes.target())?;
+        services.retain(|service_name| changed.contains(service_name));
+    }
+
+    Ok(services)
+}
+
+fn run_step(project: &Project, args: &RunArgs) -> Result<bool> {
+    let services = select_services(project, &args.plan)?;
+    let plan = project.plan(&args.plan.step, &services)?;
+
+    let executor = args.executor.executor(project);
+    let mut scheduler = Scheduler::new(executor.as_ref())
+        .with_jobs(args.jobs.unwrap_or(project.config().options.jobs))
+        .with_env(project.env(executor.as_ref()));
+    // Only apply `on_changed` policies when running on changed services
+    if args.plan.changes.base().is_some() {
+        for (step_name, step_config) in &project.config().steps {
+            scheduler = scheduler.with_on_changed(step_name, step_config.on_changed);
+        }
+    }
+
+    let options = &project.config().options;
+    let artifacts = ArtifactStore::new(project.path(), project.path().join(&options.artifacts_dir));
+    scheduler = scheduler.with_artifacts(&artifacts);
+
+    let local_cache = LocalCache::new(project.path().join(&options.cache_dir));
+    let shared_cache = match (&args.shared_cache, &options.shared_cache_dir) {
+        (Some(path), _) => Some(SharedCache::new(path)),
+        (None, Some(path)) => Some(SharedCache::new(project.path().join(path))),
+        (None, None) => None,
+    };
+    if !args.no_cache {
+        scheduler = scheduler
+            .with_cache(&local_cache)
+            .with_fingerprints(project.fingerprints(&plan)?);
+        if let Some(shared_cache) = &shared_cache {
+            scheduler = scheduler.with_cache(shared_cache);
+        }
+    }
+
+    let events_reporter = args.events_reporter()?;
+    if let Some(reporter) = &events_reporter {
+        scheduler = scheduler.with_reporter(reporter);
+    }
+    let junit_reporter = JUnitReporter::new();
+    if args.junit.is_some() {
+        scheduler = scheduler.with_reporter(&junit_reporter);
+    }
+
+    // Write the JUnit report with the results collected so far, even if the
+    // run stopped with an error
+    let results = scheduler.run(&plan);
+    if let Some(path) = &args.junit {
+        junit_reporter
+            .write(File::create(path).map_err(|source| Error::CannotWriteReport { source })?)?;
+    }
+    let results = results?;
+    let success = results.len() == plan.len() && results.iter().all(StepResult::success);
+    if args.events_to_stdout() {
+        return Ok(success);
+    }
+
+    for result in &results {
+        print_result(result);
+    }
+    if results.len() < plan.len() {
+        println!(
+            "{} step:service pair(s) not run due to failures",
+            plan.len() - results.len()
+        );
+    }
+
+    Ok(success)
+}
+
+fn check_step(project: &Project, args: &CheckArgs) -> Result<bool> {
+    let services = select_services(project, &args.plan)?;
+    let plan = project.plan(&args.plan.step, &services)?;
+
+    let executor = args.executor.executor(project);
+    let runner = Runner::new(executor.as_ref());
+    let artifacts = ArtifactStore::new(
+        project.path(),
+        project.path().join(&project.config().options.artifacts_dir),
+    );
+    let project_env = project.env(executor.as_ref());
+
+    let mut success = true;
+    for step in plan.steps() {
+        let mut env = step.script_env(&project_env);
+        env.extend(artifacts.env(executor.as_ref(), step));
+        let (status, output) = runner.check(step, &env)?;
+        let label = match status {
+            CheckStatus::UpToDate => "up-to-date",
+            CheckStatus::NeedsRun => "needs-run",
+            CheckStatus::Failed => {
+                success = false;
+                "FAILED"
+            }
+        };
+        println!("{:<12}{}", label, step.name);
+        if let (CheckStatus::Failed, Some(output)) = (status, output) {
+            print_output(&output.stdout, &output.stderr);
+        }
+    }
+
+    Ok(success)
+}
+
+fn print_result(result: &StepResult) {
+    let label = match result.status {
+        StepStatus::Skipped => "skipped",
+        StepStatus::UpToDate => "up-to-date",
+        StepStatus::Succeeded => "ok",
+        StepStatus::Cached => "cached",
+        StepStatus::Failed => "FAILED",
+    };
+    println!(
+        "{:<12}{} ({:.2}s)",
+        label,
+        result.name,
+        result.duration().as_secs_f64()
+    );
+
+    if result.status == StepStatus::Failed {
+        for output in result.outputs() {
+            print_output(&output.stdout, &output.stderr);
+        }
+    }
+}
+
+fn print_output(stdout: &[u8], stderr: &[u8]) {
+    for line in String::from_utf8_lossy(stdout).lines() {
+        println!("    | {}", line);
+    }
+    for line in String::from_utf8_lossy(stderr).lines() {
+        println!("    ! {}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli() {
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,149 @@
+use crate::ServiceStep;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Execution plan for a set of step:service pairs
+///
+/// Pairs are grouped in layers. Pairs within a layer only depend on pairs
+/// from previous layers, and can therefore run in any order.
+#[derive(Clone, Debug, Default)]
+pub struct Plan {
+    layers: Vec<Vec<ServiceStep>>,
+
+    /// Direct dependencies of each step:service pair within the plan
+    dependencies: HashMap<String, Vec<String>>,
+}
+
+impl Plan {
+    /// Create a plan from layers of `ServiceStep`s
+    pub fn new(layers: Vec<Vec<ServiceStep>>) -> Self {
+        Self {
+            layers,
+            dependencies: Default::default(),
+        }
+    }
+
+    /// Set the direct dependencies of the step:service pairs
+    pub fn with_dependencies(mut self, dependencies: HashMap<String, Vec<String>>) -> Self {
+        self.dependencies = dependencies;
+        self
+    }
+
+    /// Direct dependencies of a step:service pair within the plan
+    pub fn dependencies(&self, name: &str) -> &[String] {
+        self.dependencies
+            .get(name)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Layers of the plan, in execution order
+    pub fn layers(&self) -> &[Vec<ServiceStep>] {
+        &self.layers
+    }
+
+    /// Iterate over all `ServiceStep`s of the plan, in execution order
+    pub fn steps(&self) -> impl Iterator<Item = &ServiceStep> {
+        self.layers.iter().flatten()
+    }
+
+    /// Number of `ServiceStep`s in the plan
+    pub fn len(&self) -> usize {
+        self.layers.iter().map(Vec::len).sum()
+    }
+
+    /// Check if the plan doesn't contain any `ServiceStep`
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Dependency graph of the plan in the Graphviz DOT format
+    ///
+    /// Edges go from each step:service pair to the pairs it depends on.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        for step in self.steps() {
+            dot.push_str(&format!("  \"{}\";\n", step.name));
+            for dependency in self.dependencies(&step.name) {
+                dot.push_str(&format!("  \"{}\" -> \"{}\";\n", step.name, dependency));
+            }
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for (index, layer) in self.layers.iter().enumerate() {
+            writeln!(f, "layer {}:", index + 1)?;
+            for step in layer {
+                writeln!(f, "  {}", step.name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_step;
+
+    #[test]
+    fn plan() {
+        let plan = Plan::new(vec![
+            vec![
+                build_step("a", "build", &Default::default()),
+                build_step("b", "build", &Default::default()),
+            ],
+            vec![build_step("a", "deploy", &Default::default())],
+        ]);
+
+        assert_eq!(plan.len(), 3);
+        assert!(!plan.is_empty());
+        assert_eq!(
+            plan.steps()
+                .map(|step| step.name.as_str())
+                .collect::<Vec<_>>(),
+            ["build:a", "build:b", "deploy:a"]
+        );
+        assert_eq!(
+            plan.to_string(),
+            "layer 1:\n  build:a\n  build:b\nlayer 2:\n  deploy:a\n"
+        );
+        assert_eq!(plan.dependencies("deploy:a"), [] as [String; 0]);
+    }
+
+    #[test]
+    fn plan_dependencies() {
+        let plan = Plan::new(vec![
+            vec![build_step("a", "build", &Default::default())],
+            vec![build_step("a", "deploy", &Default::default())],
+        ])
+        .with_dependencies(
+            vec![(String::from("deploy:a"), vec![String::from("build:a")])]
+                .into_iter()
+                .collect(),
+        );
+
+        assert_eq!(plan.dependencies("deploy:a"), ["build:a"]);
+        assert_eq!(plan.dependencies("build:a"), [] as [String; 0]);
+        assert_eq!(
+            plan.to_dot(),
+            "digraph {\n  \"build:a\";\n  \"deploy:a\";\n  \"deploy:a\" -> \"build:a\";\n}\n"
+        );
+    }
+
+    #[test]
+    fn plan_empty() {
+        let plan = Plan::default();
+
+        assert_eq!(plan.len(), 0);
+        assert!(plan.is_empty());
+        assert_eq!(plan.to_string(), "");
+        assert_eq!(plan.to_dot(), "digraph {\n}\n");
+    }
+}
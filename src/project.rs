@@ -1,22 +1,50 @@
 use crate::{
-    config::{ProjectConfig, RecipeConfig, ServiceConfig},
-    utils::load_config,
-    Error, Result, Service,
+    cache::Fingerprinter,
+    changes::{changed_files, input_files, DiffBase, DiffTarget},
+    config::{
+        ProjectConfig, RecipeConfig, RecipeParamConfig, RecipeRef, ScriptConfig, ServiceConfig,
+    },
+    graph::Graph,
+    plan::Plan,
+    template::render_prefix,
+    utils::{env_var_name, load_config, load_env_file},
+    Error, Executor, Result, Script, Service, ServiceStep,
 };
 use git2::Repository;
-use std::cell::Cell;
-use std::collections::HashMap;
-use std::fs::read_dir;
-use std::path::{Path, PathBuf};
-use std::rc::Rc;
-
+use glob::{MatchOptions, Pattern};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{read, read_dir, read_link, symlink_metadata};
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[allow(clippy::redundant_static_lifetimes)]
 const PROJECT_CONFIG_FILENAME: &'static str = "orcs.toml";
-const SERVICE_CONFIG_FILENAME: &'static str = "orcs.toml";
-const SERVICE_FOLDER: &'static str = "srv";
-const RECIPE_FOLDER: &'static str = "rcp";
+#[allow(clippy::redundant_static_lifetimes)]
+pub(crate) const SERVICE_CONFIG_FILENAME: &'static str = "orcs.toml";
+#[allow(clippy::redundant_static_lifetimes)]
+pub(crate) const SERVICE_FOLDER: &'static str = "srv";
+#[allow(clippy::redundant_static_lifetimes)]
+pub(crate) const RECIPE_FOLDER: &'static str = "rcp";
+
+/// Environment variable containing the name of the project
+const PROJECT_VAR: &str = "ORCS_PROJECT";
+/// Environment variable containing the root folder of the project
+const ROOT_VAR: &str = "ORCS_ROOT";
+
+/// Options for matching files against the additional inputs of a service
+const INPUT_MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
 
 #[derive(Default)]
 /// Orcs Project
+///
+/// Projects can be shared between threads: services and recipes are cached
+/// behind locks and handed out as `Arc`s.
 pub struct Project {
     /// Root folder for the project
     path: PathBuf,
@@ -24,12 +52,15 @@ pub struct Project {
     /// Configuration file for the project
     config: ProjectConfig,
 
+    /// Environment variables from the project configuration and its env file
+    env: BTreeMap<String, String>,
+
     /// Loaded services for the project.
     ///
     /// Users shouldn't interact with this directly (thus this is set to
     /// private), but use the `get_service()` and `get_all_services()` functions
     /// to retrieve one or multiple services.
-    services: Cell<HashMap<String, Rc<Service>>>,
+    services: Mutex<HashMap<String, Arc<Service>>>,
 
     /// Flag if we've already loaded all services or not.
     ///
@@ -37,10 +68,12 @@ pub struct Project {
     /// `get_all_services()` call, having data in the service HashMap is not a
     /// good indicator if we have all services loaded already. Therefore, we
     /// need a flag to load this explicitely.
-    services_all_loaded: Cell<bool>,
+    ///
+    /// This is only updated while holding the lock on `services`.
+    services_all_loaded: AtomicBool,
 
     /// Loaded recipes for the project.
-    recipes: Cell<HashMap<String, Rc<RecipeConfig>>>,
+    recipes: Mutex<HashMap<String, Arc<ResolvedRecipe>>>,
 }
 
 impl Project {
@@ -55,22 +88,109 @@ impl Project {
         let path = path.into();
 
         // Loading configuration
-        let config = load_config(path.join(PROJECT_CONFIG_FILENAME))?;
+        let config: ProjectConfig = load_config(path.join(PROJECT_CONFIG_FILENAME))?;
+        let env = with_env_file(&config.env, config.env_file.as_deref(), &path)?;
 
         // Return the project
         let project = Self {
             path: path.clone(),
             config,
+            env,
 
             ..Default::default()
         };
 
-        // Validate the project
-        project.validate()?;
+        // Check if the project is a repository
+        Repository::open(&project.path).map_err(|source| Error::ProjectIsNotGitRepo {
+            path: project.path.clone(),
+            source,
+        })?;
+
+        // Check that the step dependency graph is valid
+        project.step_graph()?;
+
+        // Only the project root is available to scripts running in
+        // containers
+        let artifacts_dir = &project.config.options.artifacts_dir;
+        if !Path::new(artifacts_dir)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(Error::ArtifactsDirOutsideProject {
+                path: artifacts_dir.clone(),
+            });
+        }
 
         Ok(project)
     }
 
+    /// Find and load the project containing a path
+    ///
+    /// This walks up from `path` to the nearest folder containing both a
+    /// project configuration file and a git repository, as service folders
+    /// also contain configuration files.
+    pub fn discover<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        for folder in path.ancestors() {
+            if folder.join(PROJECT_CONFIG_FILENAME).is_file() && folder.join(".git").exists() {
+                return Self::from_path(folder);
+            }
+        }
+
+        Err(Error::ProjectNotFound {
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Root folder of the project
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Configuration of the project
+    pub fn config(&self) -> &ProjectConfig {
+        &self.config
+    }
+
+    /// Environment variables shared by the scripts of all step:service pairs
+    ///
+    /// This contains the variables from the project configuration, as well
+    /// as `ORCS_PROJECT` and `ORCS_ROOT`, with the project root as seen by
+    /// scripts run through `executor`.
+    pub fn env(&self, executor: &dyn Executor) -> BTreeMap<String, String> {
+        let mut env = self.env.clone();
+        env.insert(PROJECT_VAR.to_string(), self.config.name.clone());
+        env.insert(
+            ROOT_VAR.to_string(),
+            executor.script_path(&self.path).to_string_lossy().into(),
+        );
+        env
+    }
+
+    /// Variables for the placeholders in the scripts of a step:service pair
+    ///
+    /// This contains:
+    /// * `project.name`, `service.name` and `step.name`,
+    /// * `env.<NAME>` for each environment variable from the project, recipe
+    ///   and service configurations.
+    pub fn template_vars(&self, step: &ServiceStep) -> BTreeMap<String, String> {
+        let mut env = self.env.clone();
+        env.extend(step.env().clone());
+
+        let mut vars: BTreeMap<String, String> = env
+            .into_iter()
+            .map(|(key, value)| (format!("env.{}", key), value))
+            .collect();
+        vars.insert(String::from("project.name"), self.config.name.clone());
+        vars.insert(String::from("service.name"), step.service_name.clone());
+        vars.insert(String::from("step.name"), step.step_name.clone());
+        vars
+    }
+
     // TODO
     // /// Create a new project from scratch
     // pub fn create<P>(path: P) -> Result<Self>
@@ -80,77 +200,557 @@ impl Project {
 
     // }
 
-    /// Check if the project is correct
-    fn validate(&self) -> Result<()> {
+    /// Check if the project and all its services are correct
+    ///
+    /// Loading a project only checks the project configuration, so that a
+    /// broken service doesn't prevent working with the other ones. This loads
+    /// every service, and is also done when creating a plan.
+    pub fn validate(&self) -> Result<()> {
         // TODO:
         // * Check for reserved names in steps
-        // * Check that the step dependency graph is acyclic and all values
-        //   exist
 
-        // Check if the project is a repository
-        Repository::open(&self.path).map_err(|source| Error::ProjectIsNotGitRepo {
-            path: self.path.clone(),
-            source,
-        })?;
+        // Check that the dependency graphs are acyclic and that all steps and
+        // services exist
+        self.service_step_graph()?;
+
+        // Check that the outputs of different step:service pairs don't share
+        // environment variables
+        let services = self.get_all_services()?;
+        let names: BTreeSet<&String> = services
+            .values()
+            .flat_map(|service| service.steps().map(|step| &step.name))
+            .collect();
+        let mut var_names: HashMap<String, &String> = Default::default();
+        for name in names {
+            if let Some(other) = var_names.insert(env_var_name(name), name) {
+                return Err(Error::OutputVarCollision {
+                    name: name.clone(),
+                    other: other.clone(),
+                });
+            }
+        }
 
         Ok(())
     }
 
+    /// Create an execution plan for a step on a set of services
+    ///
+    /// The plan contains the step:service pair of each service implementing
+    /// the step, as well as all the pairs they depend on, including pairs
+    /// from other services.
+    ///
+    /// Steps marked with `skip_run` are left out of the plan, unless they are
+    /// the requested step or, if the requested step is marked with `skip_run`
+    /// too, one of its dependencies. The pairs they depend on are still part
+    /// of the plan.
+    ///
+    /// Placeholders in the scripts of the pairs are rendered with the
+    /// variables from `template_vars`.
+    pub fn plan(&self, step_name: &str, service_names: &[String]) -> Result<Plan> {
+        if !self.config.steps.contains_key(step_name) {
+            return Err(Error::MissingStep {
+                name: step_name.to_string(),
+            });
+        }
+
+        self.validate()?;
+        let graph = self.service_step_graph()?;
+        let services = self.get_all_services()?;
+
+        // Find the step:service pairs requested
+        let mut roots: Vec<&str> = Default::default();
+        for service_name in service_names {
+            let service = services
+                .get(service_name)
+                .ok_or_else(|| Error::MissingService {
+                    name: service_name.clone(),
+                })?;
+            if let Some(step) = service.get_step(step_name) {
+                roots.push(&step.name);
+            }
+        }
+
+        // Steps that should only run when explicitly requested. Requesting
+        // such a step also requests the steps it depends on.
+        let requested_steps: BTreeSet<String> = if self.config.steps[step_name].skip_run {
+            self.step_graph()?
+                .layers(vec![step_name], |_| true)
+                .into_iter()
+                .flatten()
+                .collect()
+        } else {
+            std::iter::once(step_name.to_string()).collect()
+        };
+        let skipped_steps: BTreeSet<&str> = self
+            .config
+            .steps
+            .iter()
+            .filter(|(name, step_config)| step_config.skip_run && !requested_steps.contains(*name))
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        // Retrieve the `ServiceStep`s for each layer
+        let names = graph.layers(roots, |name| {
+            let (step_name, _) = name
+                .split_once(':')
+                .expect("failed to parse step:service pair");
+            !skipped_steps.contains(step_name)
+        });
+        let planned: BTreeSet<&str> = names.iter().flatten().map(String::as_str).collect();
+        let dependencies = planned
+            .iter()
+            .map(|name| {
+                let dependencies = graph
+                    .filtered_dependencies(name, |dependency| planned.contains(dependency))
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                (name.to_string(), dependencies)
+            })
+            .collect();
+        let layers = names
+            .iter()
+            .map(|layer| {
+                layer
+                    .iter()
+                    .map(|name| {
+                        let (step_name, service_name) = name
+                            .split_once(':')
+                            .expect("failed to parse step:service pair");
+                        let step = services[service_name]
+                            .get_step(step_name)
+                            .expect("failed to get service step");
+                        step.render(&self.template_vars(step))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Plan::new(layers).with_dependencies(dependencies))
+    }
+
+    /// Find the services with changes between `base` and `target`
+    ///
+    /// A service changed if:
+    /// * any file within its folder changed,
+    /// * any of its recipe files changed,
+    /// * any file matching its additional inputs changed,
+    /// * the project configuration file changed and the
+    ///   `config_changes_all` option is enabled.
+    ///
+    /// Services whose configuration cannot be loaded are only checked for
+    /// changes within their folder.
+    pub fn changed_services(
+        &self,
+        base: &DiffBase,
+        target: &DiffTarget,
+    ) -> Result<BTreeSet<String>> {
+        let service_names = self.service_names()?;
+        let services: HashMap<&String, Arc<Service>> = service_names
+            .iter()
+            .filter_map(|service_name| {
+                self.get_service(service_name)
+                    .ok()
+                    .map(|service| (service_name, service))
+            })
+            .collect();
+        let service_folder = self.path.join(SERVICE_FOLDER);
+
+        // Compile the additional input patterns
+        let mut inputs: Vec<(&String, Vec<Pattern>)> = Default::default();
+        for (service_name, service) in &services {
+            inputs.push((service_name, Self::input_patterns(service)?));
+        }
+
+        let mut changed: BTreeSet<String> = Default::default();
+        let mut changed_recipes: BTreeSet<String> = Default::default();
+        for file in changed_files(&self.path, base, target)? {
+            // Additional inputs
+            for (service_name, patterns) in &inputs {
+                if patterns
+                    .iter()
+                    .any(|pattern| pattern.matches_path_with(&file, INPUT_MATCH_OPTIONS))
+                {
+                    changed.insert(service_name.to_string());
+                }
+            }
+
+            // Project configuration file
+            if file == Path::new(PROJECT_CONFIG_FILENAME) {
+                if self.config.options.config_changes_all {
+                    return Ok(service_names);
+                }
+                continue;
+            }
+
+            // Recipe files
+            if let Ok(recipe_path) = file.strip_prefix(RECIPE_FOLDER) {
+                if recipe_path.extension().is_some_and(|ext| ext == "toml") {
+                    changed_recipes.insert(
+                        recipe_path
+                            .with_extension("")
+                            .to_string_lossy()
+                            .replace("\\", "/"),
+                    );
+                }
+                continue;
+            }
+
+            // Service files
+            let path = self.path.join(file);
+            for folder in path.ancestors().skip(1) {
+                if !folder.starts_with(&service_folder) || folder == service_folder {
+                    break;
+                }
+                let service_name = self.get_service_name(folder);
+                if service_names.contains(&service_name) {
+                    changed.insert(service_name);
+                    break;
+                }
+            }
+        }
+
+        // Services using a recipe that changed
+        for (service_name, service) in &services {
+            if service
+                .recipes()
+                .iter()
+                .any(|recipe_name| changed_recipes.contains(recipe_name))
+            {
+                changed.insert(service_name.to_string());
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Compute the fingerprints of the cacheable step:service pairs of a plan
+    ///
+    /// The fingerprint of a step:service pair covers:
+    /// * its resolved check and run scripts, its container image, its
+    ///   declared outputs and its environment variables,
+    /// * the files not ignored by git in its service folder or matching its
+    ///   additional inputs,
+    /// * the recipe files of its service,
+    /// * the fingerprints of the step:service pairs it depends on.
+    ///
+    /// Pairs for steps with `cache` disabled are left out.
+    pub fn fingerprints(&self, plan: &Plan) -> Result<HashMap<String, String>> {
+        let mut state = FingerprintState {
+            graph: self.service_step_graph()?,
+            services: self.get_all_services()?,
+            files: input_files(&self.path)?,
+            service_inputs: Default::default(),
+            steps: Default::default(),
+        };
+
+        let mut fingerprints: HashMap<String, String> = Default::default();
+        for step in plan.steps() {
+            let cache = self
+                .config
+                .steps
+                .get(&step.step_name)
+                .is_some_and(|step_config| step_config.cache);
+            if cache {
+                let fingerprint = self.step_fingerprint(&mut state, &step.name)?;
+                fingerprints.insert(step.name.clone(), fingerprint);
+            }
+        }
+
+        Ok(fingerprints)
+    }
+
+    /// Compute the fingerprint of a step:service pair
+    fn step_fingerprint(&self, state: &mut FingerprintState, name: &str) -> Result<String> {
+        if let Some(fingerprint) = state.steps.get(name) {
+            return Ok(fingerprint.clone());
+        }
+
+        let (step_name, service_name) = name
+            .split_once(':')
+            .expect("failed to parse step:service pair");
+        let service = state.services[service_name].clone();
+        let step = service
+            .get_step(step_name)
+            .expect("failed to get service step");
+
+        // Scripts are fingerprinted as they run, with their placeholders
+        // rendered
+        let rendered = step.render(&self.template_vars(step))?;
+
+        let mut fingerprinter = Fingerprinter::default();
+        fingerprinter.add(name);
+        for script in [rendered.check(), rendered.run()].iter() {
+            match script {
+                Script::Script(script) => fingerprinter.add("script").add(script),
+                Script::Override(value) => fingerprinter.add("override").add(value.to_string()),
+                Script::None => fingerprinter.add("none"),
+            };
+        }
+        fingerprinter.add(
+            step.container_image()
+                .unwrap_or(&self.config.options.container_image),
+        );
+        for output in step.outputs() {
+            fingerprinter.add("output").add(output);
+        }
+        let mut env = self.env.clone();
+        env.extend(step.env().clone());
+        for (key, value) in &env {
+            fingerprinter.add("env").add(key).add(value);
+        }
+        fingerprinter.add(self.service_inputs_fingerprint(state, &service)?);
+
+        let dependencies: Vec<String> = state.graph.dependencies(name).cloned().collect();
+        for dependency in dependencies {
+            fingerprinter
+                .add(&dependency)
+                .add(self.step_fingerprint(state, &dependency)?);
+        }
+
+        let fingerprint = fingerprinter.finish();
+        state.steps.insert(name.to_string(), fingerprint.clone());
+        Ok(fingerprint)
+    }
+
+    /// Compute the fingerprint of the input files of a service
+    ///
+    /// This covers the files not ignored by git within the service folder or
+    /// matching its additional inputs, and its recipe files.
+    fn service_inputs_fingerprint(
+        &self,
+        state: &mut FingerprintState,
+        service: &Service,
+    ) -> Result<String> {
+        if let Some(fingerprint) = state.service_inputs.get(&service.name) {
+            return Ok(fingerprint.clone());
+        }
+
+        let mut fingerprinter = Fingerprinter::default();
+
+        // Service files and additional inputs
+        let service_folder = Path::new(SERVICE_FOLDER).join(&service.name);
+        let patterns = Self::input_patterns(service)?;
+        for file in &state.files {
+            if file.starts_with(&service_folder)
+                || patterns
+                    .iter()
+                    .any(|pattern| pattern.matches_path_with(file, INPUT_MATCH_OPTIONS))
+            {
+                fingerprinter
+                    .add(file.to_string_lossy().as_bytes())
+                    .add(read_input_file(self.path.join(file))?);
+            }
+        }
+
+        // Recipe files
+        for recipe_name in service.recipes() {
+            let path = self
+                .path
+                .join(RECIPE_FOLDER)
+                .join(format!("{}.toml", recipe_name));
+            fingerprinter.add(recipe_name).add(read_input_file(path)?);
+        }
+
+        let fingerprint = fingerprinter.finish();
+        state
+            .service_inputs
+            .insert(service.name.clone(), fingerprint.clone());
+        Ok(fingerprint)
+    }
+
+    /// Compile the additional input patterns of a service
+    fn input_patterns(service: &Service) -> Result<Vec<Pattern>> {
+        service
+            .inputs()
+            .iter()
+            .map(|input| {
+                Pattern::new(input).map_err(|source| Error::InvalidInputPattern {
+                    service: service.name.clone(),
+                    pattern: input.clone(),
+                    source,
+                })
+            })
+            .collect()
+    }
+
+    /// Build the dependency graph between the project steps
+    fn step_graph(&self) -> Result<Graph> {
+        let mut graph = Graph::default();
+
+        let steps: BTreeMap<_, _> = self.config.steps.iter().collect();
+        for (step_name, step_config) in steps {
+            graph.add_node(step_name);
+            for dependency in &step_config.depends_on {
+                if !self.config.steps.contains_key(dependency) {
+                    return Err(Error::UnknownStep {
+                        name: dependency.clone(),
+                        referenced_by: step_name.clone(),
+                    });
+                }
+                graph.add_edge(step_name, dependency);
+            }
+        }
+
+        match graph.find_cycle() {
+            Some(path) => Err(Error::DependencyCycle { path }),
+            None => Ok(graph),
+        }
+    }
+
+    /// Build the dependency graph between all step:service pairs
+    ///
+    /// A step:service pair depends on:
+    /// * the same service for the steps its project step depends on,
+    /// * the step:service pairs in its `depends_on`.
+    ///
+    /// Dependencies on a step that a service doesn't implement are replaced
+    /// by the dependencies of that step.
+    fn service_step_graph(&self) -> Result<Graph> {
+        let step_graph = self.step_graph()?;
+        let services = self.get_all_services()?;
+        let mut graph = Graph::default();
+
+        let services: BTreeMap<_, _> = services.iter().collect();
+        for service in services.values() {
+            let steps: BTreeMap<_, _> = service
+                .steps()
+                .map(|step| (&step.step_name, step))
+                .collect();
+            for step in steps.values() {
+                if !step_graph.contains(&step.step_name) {
+                    return Err(Error::UnknownStep {
+                        name: step.step_name.clone(),
+                        referenced_by: step.name.clone(),
+                    });
+                }
+                graph.add_node(&step.name);
+
+                // Project-level dependencies
+                for dependency in Self::service_dependencies(&step_graph, service, &step.step_name)
+                {
+                    graph.add_edge(&step.name, &dependency);
+                }
+
+                // Service-level dependencies
+                for dependency in step.depends_on() {
+                    let (dep_step_name, dep_service_name) =
+                        dependency
+                            .split_once(':')
+                            .ok_or_else(|| Error::InvalidDependency {
+                                name: dependency.clone(),
+                                referenced_by: step.name.clone(),
+                            })?;
+                    if !step_graph.contains(dep_step_name) {
+                        return Err(Error::UnknownStep {
+                            name: dep_step_name.to_string(),
+                            referenced_by: step.name.clone(),
+                        });
+                    }
+                    let dep_service =
+                        services.get(&dep_service_name.to_string()).ok_or_else(|| {
+                            Error::UnknownService {
+                                name: dep_service_name.to_string(),
+                                referenced_by: step.name.clone(),
+                            }
+                        })?;
+                    if dep_service.get_step(dep_step_name).is_some() {
+                        graph.add_edge(&step.name, dependency);
+                    } else {
+                        for dependency in
+                            Self::service_dependencies(&step_graph, dep_service, dep_step_name)
+                        {
+                            graph.add_edge(&step.name, &dependency);
+                        }
+                    }
+                }
+            }
+        }
+
+        match graph.find_cycle() {
+            Some(path) => Err(Error::DependencyCycle { path }),
+            None => Ok(graph),
+        }
+    }
+
+    /// Find the step:service pairs of a service that a step depends on at
+    /// the project level
+    ///
+    /// If the service doesn't implement one of the dependencies, this looks
+    /// at the dependencies of that step instead.
+    fn service_dependencies(step_graph: &Graph, service: &Service, step_name: &str) -> Vec<String> {
+        let mut dependencies: Vec<String> = Default::default();
+        let mut visited: BTreeSet<&str> = Default::default();
+        let mut queue: Vec<&str> = step_graph
+            .dependencies(step_name)
+            .map(|d| d.as_str())
+            .collect();
+
+        while let Some(dep_step_name) = queue.pop() {
+            if !visited.insert(dep_step_name) {
+                continue;
+            }
+            match service.get_step(dep_step_name) {
+                Some(dep_step) => dependencies.push(dep_step.name.clone()),
+                None => queue.extend(step_graph.dependencies(dep_step_name).map(|d| d.as_str())),
+            }
+        }
+
+        dependencies
+    }
+
     /// Get a service from its name
     ///
     /// If the service was already loaded before, return it from the Project's
     /// internal store, otherwise fetch it.
-    pub fn get_service(&self, service_name: &str) -> Result<Rc<Service>> {
-        let mut services = self.services.take();
+    pub fn get_service(&self, service_name: &str) -> Result<Arc<Service>> {
+        let mut services = self.services.lock().expect("failed to lock services");
         // Only load the service if we haven't loaded it already
         if !services.contains_key(service_name) {
-            let service = Rc::new(self.load_service(service_name)?);
+            let service = Arc::new(self.load_service(service_name)?);
 
             services.insert(service_name.to_string(), service);
         }
 
-        let service = services
+        Ok(services
             .get(service_name)
             .expect("failed to get service")
-            .clone();
-
-        // Set back the HashMap in the Cell
-        self.services.set(services);
+            .clone())
+    }
 
-        Ok(service)
+    /// Return the names of all services for a given project
+    ///
+    /// This doesn't load the services, so services with an invalid
+    /// configuration are listed too.
+    pub fn service_names(&self) -> Result<BTreeSet<String>> {
+        self.scan_service_names(self.path.join(SERVICE_FOLDER))
     }
 
     /// Return all services for a given project
     ///
     /// The first time this method is called, it will scan the project folder
-    /// for all projects and save it into the Project's internal state (thus
-    /// the need to pass a mutable reference).
-    pub fn get_all_services(&self) -> Result<HashMap<String, Rc<Service>>> {
-        let mut services = self.services.take();
-        if !self.services_all_loaded.get() {
+    /// for all projects and save it into the Project's internal state.
+    pub fn get_all_services(&self) -> Result<HashMap<String, Arc<Service>>> {
+        let mut services = self.services.lock().expect("failed to lock services");
+        if !self.services_all_loaded.load(Ordering::SeqCst) {
             // Load all services
             services.extend(self.scan_services(self.path.join(SERVICE_FOLDER))?);
 
-            self.services_all_loaded.set(true);
+            self.services_all_loaded.store(true, Ordering::SeqCst);
         }
 
         // Return all services
         //
-        // This clones the `HashMap` and `Rc`s, but not the internal `Service`
+        // This clones the `HashMap` and `Arc`s, but not the internal `Service`
         // structs.
-        let retval = services.clone();
-
-        // Set back the HashMap in the Cell
-        self.services.set(services);
-
-        Ok(retval)
+        Ok(services.clone())
     }
 
     // /// Get a recipe from its name
     // ///
     // /// If the recipe was already loaded before, return it from the Project's
     // /// internal store, otherwise fetch it.
-    // fn get_recipe(&self, recipe_name: &str) -> Result<Rc<RecipeConfig>> {
+    // fn get_recipe(&self, recipe_name: &str) -> Result<Arc<RecipeConfig>> {
     //     let mut recipes = self.recipes.get_mut();
     //     // Only load the recipe if it wasn't loaded previously
     //     if !recipes.contains_key(recipe_name) {
@@ -166,20 +766,18 @@ impl Project {
 
     /// Retrieve multiple recipes at once
     ///
-    /// This will return the recipes in the same order as the names provided.
-    fn get_recipes(&self, recipe_names: &[String]) -> Result<Vec<Rc<RecipeConfig>>> {
-        let mut recipes = self.recipes.take();
+    /// This will return the recipes in the same order as the names provided,
+    /// with the recipes they extend resolved.
+    fn get_recipes(&self, recipe_names: &[String]) -> Result<Vec<Arc<ResolvedRecipe>>> {
+        let mut recipes = self.recipes.lock().expect("failed to lock recipes");
         // First loop to perform mutable operations (loading and storing the
         // recipes that we haven't scanned yet).
         for recipe_name in recipe_names {
-            if !recipes.contains_key(recipe_name) {
-                let recipe = self.load_recipe_config(recipe_name)?;
-                recipes.insert(recipe_name.to_string(), Rc::new(recipe));
-            }
+            self.resolve_recipe(&mut recipes, recipe_name, &mut Vec::new())?;
         }
 
         // Second loop to retrieve the recipes requested
-        let mut req_recipes: Vec<Rc<RecipeConfig>> = Default::default();
+        let mut req_recipes: Vec<Arc<ResolvedRecipe>> = Default::default();
         for recipe_name in recipe_names {
             req_recipes.push(
                 recipes
@@ -189,68 +787,154 @@ impl Project {
             );
         }
 
-        // Set back the HashMap in the Cell
-        self.recipes.set(recipes);
-
         Ok(req_recipes)
     }
 
+    /// Load a recipe and the recipes it extends, if they are not loaded yet
+    ///
+    /// `stack` contains the recipes being resolved, to detect cycles.
+    fn resolve_recipe(
+        &self,
+        recipes: &mut HashMap<String, Arc<ResolvedRecipe>>,
+        recipe_name: &str,
+        stack: &mut Vec<String>,
+    ) -> Result<Arc<ResolvedRecipe>> {
+        if let Some(recipe) = recipes.get(recipe_name) {
+            return Ok(recipe.clone());
+        }
+        if let Some(index) = stack.iter().position(|name| name == recipe_name) {
+            let mut path = stack[index..].to_vec();
+            path.push(recipe_name.to_string());
+            return Err(Error::RecipeCycle { path });
+        }
+
+        stack.push(recipe_name.to_string());
+        let config = Arc::new(self.load_recipe_config(recipe_name)?);
+        let mut chain = vec![(recipe_name.to_string(), config.clone())];
+        // As for services, the latest recipe takes precedence over the
+        // previous ones.
+        for parent_name in config.extends.iter().rev() {
+            let parent = self.resolve_recipe(recipes, parent_name, stack)?;
+            for (name, parent_config) in &parent.chain {
+                if !chain.iter().any(|(chain_name, _)| chain_name == name) {
+                    chain.push((name.clone(), parent_config.clone()));
+                }
+            }
+        }
+        stack.pop();
+
+        let recipe = Arc::new(ResolvedRecipe { chain });
+        recipes.insert(recipe_name.to_string(), recipe.clone());
+        Ok(recipe)
+    }
+
     /// Try to find services in the given folder
     ///
     /// This will recursively scan all folders in a given `dir` to try to find
     /// all services and will return a HashMap with all values.
-    fn scan_services<P>(&self, dir: P) -> Result<HashMap<String, Rc<Service>>>
+    fn scan_services<P>(&self, dir: P) -> Result<HashMap<String, Arc<Service>>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut services: HashMap<String, Arc<Service>> = Default::default();
+
+        for service_name in self.scan_service_names(dir)? {
+            let service = self.load_service(&service_name)?;
+            services.insert(service_name, Arc::new(service));
+        }
+
+        Ok(services)
+    }
+
+    /// Try to find the names of the services in the given folder
+    ///
+    /// Unlike `scan_services()`, this doesn't load the service configuration
+    /// files.
+    fn scan_service_names<P>(&self, dir: P) -> Result<BTreeSet<String>>
     where
         P: AsRef<Path>,
     {
-        let mut services: HashMap<String, Rc<Service>> = Default::default();
+        let mut service_names: BTreeSet<String> = Default::default();
 
         let dir = dir.as_ref();
 
-        // TODO: handle errors
+        // Projects without services don't have a service folder
+        if !dir.is_dir() {
+            return Ok(service_names);
+        }
+
+        let read_error = |source| Error::CannotReadServiceFolder {
+            path: dir.to_path_buf(),
+            source,
+        };
+
         // TODO: ignore some paths
-        for entry in read_dir(dir).unwrap() {
-            let path = entry.unwrap().path();
+        for entry in read_dir(dir).map_err(read_error)? {
+            let path = entry.map_err(read_error)?.path();
 
             if !path.is_dir() {
                 continue;
             } else if path.join(SERVICE_CONFIG_FILENAME).is_file() {
                 // We found a service
-                let service_name = self.get_service_name(&path);
-                let service = self.load_service(&service_name)?;
-                services.insert(service_name, Rc::new(service));
+                service_names.insert(self.get_service_name(&path));
             } else {
                 // This is a folder, but there's no service configuration file,
                 // therefore we should scan it too
-                services.extend(self.scan_services(&path)?)
+                service_names.extend(self.scan_service_names(&path)?)
             }
         }
 
-        Ok(services)
+        Ok(service_names)
     }
 
     /// Internal method to load a service
     fn load_service(&self, service_name: &str) -> Result<Service> {
         // Load the config
-        let service_config: ServiceConfig = load_config(
-            self.path
-                .join(SERVICE_FOLDER)
-                .join(service_name)
-                .join(SERVICE_CONFIG_FILENAME),
+        let service_path = self.path.join(SERVICE_FOLDER).join(service_name);
+        let mut service_config: ServiceConfig =
+            load_config(service_path.join(SERVICE_CONFIG_FILENAME))?;
+
+        // Env files are relative to the service folder
+        service_config.env = with_env_file(
+            &service_config.env,
+            service_config.env_file.as_deref(),
+            &service_path,
         )?;
+        for step_config in service_config.steps.values_mut() {
+            step_config.env = with_env_file(
+                &step_config.env,
+                step_config.env_file.as_deref(),
+                &service_path,
+            )?;
+        }
+
+        // Parse all recipes in the service config, with their arguments
+        let recipe_names: Vec<String> = service_config
+            .recipes
+            .iter()
+            .map(|recipe| recipe.name().to_string())
+            .collect();
+        let resolved_recipes = self.get_recipes(&recipe_names)?;
+        let recipes = resolved_recipes
+            .iter()
+            .zip(&service_config.recipes)
+            .map(|(recipe, reference)| with_params(recipe, reference, service_name))
+            .collect::<Result<Vec<_>>>()?;
 
         // Create a ServiceBuilder
         let mut service = Service::from_config(service_name, &service_config);
 
-        // Parse all recipes in the service config
-        let recipes = self.get_recipes(&service_config.recipes)?;
         // Remark: We need to invert the recipe order to process them in the
         // right order. In the configuration file, the latest recipe takes
         // precedence over the previous ones. However,
         // `ServiceBuilder::with_recipe` works the other way around for
         // simplicity's sake.
-        for recipe in recipes.iter().rev() {
-            service.with_recipe(recipe);
+        // Within a recipe, the recipe itself comes first, followed by the
+        // recipes it extends.
+        for chain in recipes.iter().rev() {
+            for (recipe_name, recipe) in chain {
+                service.with_named_recipe(recipe_name, recipe);
+            }
         }
 
         Ok(service.build())
@@ -258,11 +942,20 @@ impl Project {
 
     /// Load a recipe configuration file
     fn load_recipe_config(&self, recipe_name: &str) -> Result<RecipeConfig> {
-        load_config(
-            self.path
-                .join(RECIPE_FOLDER)
-                .join(format!("{}.toml", recipe_name)),
-        )
+        let recipe_path = self.path.join(RECIPE_FOLDER);
+        let mut recipe_config: RecipeConfig =
+            load_config(recipe_path.join(format!("{}.toml", recipe_name)))?;
+
+        // Env files are relative to the recipe folder
+        for step_config in recipe_config.steps.values_mut() {
+            step_config.env = with_env_file(
+                &step_config.env,
+                step_config.env_file.as_deref(),
+                &recipe_path,
+            )?;
+        }
+
+        Ok(recipe_config)
     }
 
     /// Transform a service path into a canonical name representation
@@ -278,9 +971,135 @@ impl Project {
     }
 }
 
+/// Recipe with the recipes it extends resolved
+struct ResolvedRecipe {
+    /// Names and configurations of the recipe and of the recipes it extends,
+    /// directly or not, from the highest to the lowest precedence
+    chain: Vec<(String, Arc<RecipeConfig>)>,
+}
+
+/// Intermediate results while computing fingerprints
+struct FingerprintState {
+    graph: Graph,
+    services: HashMap<String, Arc<Service>>,
+    /// Files not ignored by git, relative to the project root
+    files: Vec<PathBuf>,
+    /// Fingerprint of the input files of each service
+    service_inputs: HashMap<String, String>,
+    /// Fingerprint of each step:service pair
+    steps: HashMap<String, String>,
+}
+
+/// Merge environment variables with the ones from an optional env file
+///
+/// The file path is relative to `folder`, and variables from `env` take
+/// precedence over the ones in the file.
+fn with_env_file(
+    env: &BTreeMap<String, String>,
+    env_file: Option<&str>,
+    folder: &Path,
+) -> Result<BTreeMap<String, String>> {
+    let mut merged = match env_file {
+        Some(env_file) => load_env_file(folder.join(env_file))?,
+        None => Default::default(),
+    };
+    merged.extend(env.clone());
+    Ok(merged)
+}
+
+/// Apply the arguments of a recipe reference to the parameters of a recipe
+///
+/// Parameters are shared between a recipe and the recipes it extends. This
+/// renders the `{{ params.<name> }}` placeholders in the scripts of all these
+/// recipes, leaving other placeholders to be rendered at plan time.
+fn with_params(
+    recipe: &ResolvedRecipe,
+    reference: &RecipeRef,
+    service_name: &str,
+) -> Result<Vec<(String, RecipeConfig)>> {
+    let mut params: BTreeMap<&String, &RecipeParamConfig> = Default::default();
+    for (_, config) in &recipe.chain {
+        for (param_name, param) in &config.params {
+            params.entry(param_name).or_insert(param);
+        }
+    }
+
+    let args = reference.args();
+    if let Some(param) = args
+        .into_iter()
+        .flat_map(|args| args.keys())
+        .find(|param| !params.contains_key(*param))
+    {
+        return Err(Error::UnknownRecipeParam {
+            service: service_name.to_string(),
+            recipe: reference.name().to_string(),
+            param: param.clone(),
+        });
+    }
+
+    let mut vars: BTreeMap<String, String> = Default::default();
+    for (param_name, param) in params {
+        let value = match args.and_then(|args| args.get(param_name)) {
+            Some(value) => value.clone(),
+            None if param.required => {
+                return Err(Error::MissingRecipeParam {
+                    service: service_name.to_string(),
+                    recipe: reference.name().to_string(),
+                    param: param_name.clone(),
+                })
+            }
+            None => param.default.clone().unwrap_or_default(),
+        };
+        vars.insert(format!("params.{}", param_name), value);
+    }
+
+    let mut chain: Vec<(String, RecipeConfig)> = Default::default();
+    for (recipe_name, config) in &recipe.chain {
+        let mut config = RecipeConfig::clone(config);
+        for (step_name, step_config) in config.steps.iter_mut() {
+            let name = format!("{}:{}", step_name, service_name);
+            let render_script = |script: &ScriptConfig| -> Result<ScriptConfig> {
+                let render = |body: &str| render_prefix(body, "params.", &vars, &name);
+                Ok(match script {
+                    ScriptConfig::Multiline(body) => ScriptConfig::Multiline(render(body)?),
+                    ScriptConfig::Array(lines) => ScriptConfig::Array(
+                        lines
+                            .iter()
+                            .map(|line| render(line))
+                            .collect::<Result<_>>()?,
+                    ),
+                    script => script.clone(),
+                })
+            };
+            step_config.check = render_script(&step_config.check)?;
+            step_config.run = render_script(&step_config.run)?;
+        }
+        chain.push((recipe_name.clone(), config));
+    }
+
+    Ok(chain)
+}
+
+/// Read the content of an input file for a fingerprint
+///
+/// Symbolic links are not followed, and files tracked by git that were
+/// removed from the working tree are treated as empty.
+fn read_input_file(path: PathBuf) -> Result<Vec<u8>> {
+    let result = match symlink_metadata(&path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            read_link(&path).map(|target| target.to_string_lossy().as_bytes().to_vec())
+        }
+        Ok(_) => read(&path),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    };
+    result.map_err(|source| Error::CannotReadInputFile { path, source })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::changes::tests::commit_all;
     use std::fs::{create_dir_all, File};
     use std::io::prelude::*;
     use tempfile::tempdir;
@@ -290,6 +1109,20 @@ mod tests {
         let project_name = "my-project";
         let step_name = "my-step";
 
+        create_project_with_config(&format!(
+            "
+        name = \"{project_name}\"
+
+        [steps.{step_name}]
+        ",
+            project_name = project_name,
+            step_name = step_name
+        ))
+    }
+
+    /// Create a project with the given configuration
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn create_project_with_config(cfg_data: &str) -> tempfile::TempDir {
         // Create a temporary project folder
         let project_dir = tempdir().expect("failed to create a temporary project folder");
         let folder = project_dir.path();
@@ -300,15 +1133,6 @@ mod tests {
         // Create a project config file
         let mut cfg_file = File::create(folder.join(PROJECT_CONFIG_FILENAME))
             .expect("failed to create the project config file");
-        let cfg_data = &format!(
-            "
-        name = \"{project_name}\"
-
-        [steps.{step_name}]
-        ",
-            project_name = project_name,
-            step_name = step_name
-        );
         cfg_file
             .write_all(cfg_data.as_bytes())
             .expect("failed to write project config file");
@@ -317,6 +1141,19 @@ mod tests {
     }
 
     fn create_service<P>(path: P, name: &str)
+    where
+        P: AsRef<Path>,
+    {
+        create_service_with_config(
+            path,
+            name,
+            "
+        [steps.my-step]
+        ",
+        );
+    }
+
+    fn create_service_with_config<P>(path: P, name: &str, config_data: &str)
     where
         P: AsRef<Path>,
     {
@@ -327,35 +1164,32 @@ mod tests {
         // Create service config file
         let mut config_file = File::create(service_path.join(SERVICE_CONFIG_FILENAME))
             .expect("unable to create service config file");
+        config_file
+            .write_all(config_data.as_bytes())
+            .expect("unable to write service config file");
+    }
+
+    fn create_recipe<P>(path: P, name: &str)
+    where
+        P: AsRef<Path>,
+    {
+        // Create recipe folder
+        let recipe_path = path.as_ref().join(RECIPE_FOLDER);
+        create_dir_all(&recipe_path).expect("unable to create recipe folder");
+
+        // Create recipe config file
+        let mut config_file = File::create(recipe_path.join(format!("{}.toml", name)))
+            .expect("unable to create recipe config file");
         let config_data = "
         [steps.my-step]
+        check = \"my-check-script\"
+        run = \"my-run-script\"
         ";
         config_file
             .write_all(config_data.as_bytes())
-            .expect("unable to write service config file");
+            .expect("unable to write recipe config file");
     }
 
-    // fn create_recipe<P>(path: P, name: &str)
-    // where
-    //     P: AsRef<Path>,
-    // {
-    //     // Create service folder
-    //     let recipe_path = path.as_ref().join(RECIPE_FOLDER);
-    //     create_dir_all(&recipe_path).expect("unable to create recipe folder");
-
-    //     // Create service config file
-    //     let mut config_file = File::create(recipe_path.join(format!("{}.toml", name)))
-    //         .expect("unable to create recipe config file");
-    //     let config_data = "
-    //     [steps.my-step]
-    //     check = \"my-check-script\"
-    //     run = \"my-run-script\"
-    //     ";
-    //     config_file
-    //         .write_all(config_data.as_bytes())
-    //         .expect("unable to write recipe config file");
-    // }
-
     #[test]
     fn load_from_path() {
         // Create the project
@@ -375,6 +1209,35 @@ mod tests {
     }
 
     #[test]
+    fn discover() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service(folder, "a/b");
+        create_dir_all(folder.join(SERVICE_FOLDER).join("a/b/src"))
+            .expect("failed to create folder");
+
+        // From a service subfolder, skipping the service configuration file
+        let project = Project::discover(folder.join(SERVICE_FOLDER).join("a/b/src"))
+            .expect("failed to discover the project");
+        assert_eq!(project.path, folder);
+
+        // From the project root
+        let project = Project::discover(folder).expect("failed to discover the project");
+        assert_eq!(project.path, folder);
+    }
+
+    #[test]
+    fn discover_not_found() {
+        let dir = tempdir().expect("failed to create a temporary folder");
+
+        match Project::discover(dir.path()) {
+            Err(Error::ProjectNotFound { path }) => assert_eq!(path, dir.path()),
+            _ => panic!("expected a project not found error"),
+        }
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrows_for_generic_args)]
     fn load_from_path_service() {
         // Create the project
         let project_name = "my-project";
@@ -404,25 +1267,1116 @@ mod tests {
             .expect("failed to get service step");
         assert_eq!(service_step.name, "my-step:my-service");
 
-        let services = project.services.take();
+        let services = project.services.lock().expect("failed to lock services");
         assert!(services.contains_key("my-service"));
     }
 
     #[test]
-    fn get_service_name() {
-        // Create a temporary project folder
-        let project_dir = tempdir().expect("failed to create a temporary project folder");
-        let folder = project_dir.path();
-        let project = Project {
-            path: folder.to_path_buf(),
-            ..Default::default()
-        };
-        let value = "a/b/c";
+    fn project_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
 
-        // Retrieve the service name
-        let result = project.get_service_name(folder.join(SERVICE_FOLDER).join(value));
+        assert_send_sync::<Project>();
+        assert_send_sync::<Service>();
+    }
 
-        // Compare the value
-        assert_eq!(result, value);
+    #[test]
+    fn get_service_error_keeps_cache() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service(folder, "my-service");
+        let project = Project::from_path(folder).expect("failed to load the project");
+
+        project
+            .get_service("my-service")
+            .expect("failed to get service");
+        assert!(project.get_service("my-other-service").is_err());
+
+        let services = project.services.lock().expect("failed to lock services");
+        assert!(services.contains_key("my-service"));
+    }
+
+    #[test]
+    fn get_service_threads() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service(folder, "a");
+        create_service(folder, "b");
+        let project = Arc::new(Project::from_path(folder).expect("failed to load the project"));
+
+        let handles: Vec<_> = ["a", "b"]
+            .iter()
+            .map(|service_name| {
+                let project = project.clone();
+                std::thread::spawn(move || {
+                    project
+                        .get_service(service_name)
+                        .expect("failed to get service")
+                        .name
+                        .clone()
+                })
+            })
+            .collect();
+
+        let names: Vec<String> = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("failed to join thread"))
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[test]
+    fn get_service_name() {
+        // Create a temporary project folder
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let folder = project_dir.path();
+        let project = Project {
+            path: folder.to_path_buf(),
+            ..Default::default()
+        };
+        let value = "a/b/c";
+
+        // Retrieve the service name
+        let result = project.get_service_name(folder.join(SERVICE_FOLDER).join(value));
+
+        // Compare the value
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn validate_unknown_step() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+
+        [steps.build]
+        [steps.deploy]
+        depends_on = [\"biuld\"]
+        ",
+        );
+
+        match Project::from_path(project_dir.path()) {
+            Err(Error::UnknownStep {
+                name,
+                referenced_by,
+            }) => {
+                assert_eq!(name, "biuld");
+                assert_eq!(referenced_by, "deploy");
+            }
+            _ => panic!("expected an unknown step error"),
+        }
+    }
+
+    #[test]
+    fn validate_artifacts_dir() {
+        let test_cases = [
+            ("artifacts", true),
+            ("./build/artifacts", true),
+            ("../artifacts", false),
+            ("artifacts/../../artifacts", false),
+            ("/tmp/artifacts", false),
+        ];
+
+        for (artifacts_dir, valid) in test_cases.iter() {
+            let project_dir = create_project_with_config(&format!(
+                "
+            name = \"my-project\"
+
+            [options]
+            artifacts_dir = \"{}\"
+            ",
+                artifacts_dir
+            ));
+
+            match (Project::from_path(project_dir.path()), valid) {
+                (Ok(_), true) => (),
+                (Err(Error::ArtifactsDirOutsideProject { path }), false) => {
+                    assert_eq!(path, *artifacts_dir)
+                }
+                _ => panic!("unexpected result for '{}'", artifacts_dir),
+            }
+        }
+    }
+
+    #[test]
+    fn validate_step_cycle() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+
+        [steps.a]
+        depends_on = [\"c\"]
+        [steps.b]
+        depends_on = [\"a\"]
+        [steps.c]
+        depends_on = [\"b\"]
+        ",
+        );
+
+        match Project::from_path(project_dir.path()) {
+            Err(Error::DependencyCycle { path }) => assert_eq!(path, ["a", "c", "b", "a"]),
+            _ => panic!("expected a dependency cycle error"),
+        }
+    }
+
+    #[test]
+    fn validate_unknown_service() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service_with_config(
+            folder,
+            "my-service",
+            "
+        [steps.my-step]
+        depends_on = [\"my-step:my-other-service\"]
+        ",
+        );
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        match project.validate() {
+            Err(Error::UnknownService {
+                name,
+                referenced_by,
+            }) => {
+                assert_eq!(name, "my-other-service");
+                assert_eq!(referenced_by, "my-step:my-service");
+            }
+            _ => panic!("expected an unknown service error"),
+        }
+    }
+
+    #[test]
+    fn validate_output_var_collision() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service(folder, "my-api");
+        create_service(folder, "my_api");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        match project.validate() {
+            Err(Error::OutputVarCollision { name, other }) => {
+                assert_eq!(name, "my-step:my_api");
+                assert_eq!(other, "my-step:my-api");
+            }
+            _ => panic!("expected an output var collision error"),
+        }
+    }
+
+    #[test]
+    fn validate_broken_service() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        let repo = Repository::open(folder).expect("failed to open repository");
+        commit_all(&repo, "first");
+        create_service(folder, "my-service");
+        create_service_with_config(folder, "my-broken-service", "[steps.my-step");
+
+        // Other services can still be used
+        let project = Project::from_path(folder).expect("failed to load the project");
+        assert_eq!(
+            project
+                .service_names()
+                .expect("failed to get service names")
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["my-broken-service", "my-service"]
+        );
+        project
+            .get_service("my-service")
+            .expect("failed to get service");
+        let changed = project
+            .changed_services(&DiffBase::Rev(String::from("HEAD")), &DiffTarget::WorkTree)
+            .expect("failed to get changed services");
+        assert_eq!(
+            changed.into_iter().collect::<Vec<_>>(),
+            ["my-broken-service", "my-service"]
+        );
+
+        match project.validate() {
+            Err(Error::CannotParseConfigFile { .. }) => (),
+            _ => panic!("expected a cannot parse config file error"),
+        }
+    }
+
+    #[test]
+    fn validate_unknown_service_step() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service_with_config(
+            folder,
+            "my-service",
+            "
+        [steps.my-other-step]
+        ",
+        );
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        match project.validate() {
+            Err(Error::UnknownStep {
+                name,
+                referenced_by,
+            }) => {
+                assert_eq!(name, "my-other-step");
+                assert_eq!(referenced_by, "my-other-step:my-service");
+            }
+            _ => panic!("expected an unknown step error"),
+        }
+    }
+
+    #[test]
+    fn validate_service_step_cycle() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+
+        [steps.build]
+        [steps.deploy]
+        depends_on = [\"build\"]
+        ",
+        );
+        let folder = project_dir.path();
+        // deploy:a -> build:a -> deploy:b -> build:b -> deploy:a
+        create_service_with_config(
+            folder,
+            "a",
+            "
+        [steps.build]
+        depends_on = [\"deploy:b\"]
+        [steps.deploy]
+        ",
+        );
+        create_service_with_config(
+            folder,
+            "b",
+            "
+        [steps.build]
+        depends_on = [\"deploy:a\"]
+        [steps.deploy]
+        ",
+        );
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        match project.validate() {
+            Err(Error::DependencyCycle { path }) => {
+                assert_eq!(
+                    path,
+                    ["build:a", "deploy:b", "build:b", "deploy:a", "build:a"]
+                )
+            }
+            _ => panic!("expected a dependency cycle error"),
+        }
+    }
+
+    #[test]
+    fn plan() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+
+        [steps.build]
+        [steps.deploy]
+        depends_on = [\"build\"]
+        ",
+        );
+        let folder = project_dir.path();
+        create_service_with_config(
+            folder,
+            "a",
+            "
+        [steps.build]
+        [steps.deploy]
+        depends_on = [\"deploy:b\"]
+        ",
+        );
+        create_service_with_config(
+            folder,
+            "b",
+            "
+        [steps.build]
+        [steps.deploy]
+        ",
+        );
+        create_service_with_config(
+            folder,
+            "c",
+            "
+        [steps.build]
+        ",
+        );
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+
+        let plan = project
+            .plan("deploy", &[String::from("a")])
+            .expect("failed to create plan");
+        let layers: Vec<Vec<&str>> = plan
+            .layers()
+            .iter()
+            .map(|layer| layer.iter().map(|step| step.name.as_str()).collect())
+            .collect();
+        assert_eq!(
+            layers,
+            vec![
+                vec!["build:a", "build:b"],
+                vec!["deploy:b"],
+                vec!["deploy:a"]
+            ]
+        );
+        assert_eq!(plan.dependencies("deploy:a"), ["build:a", "deploy:b"]);
+        assert_eq!(plan.dependencies("deploy:b"), ["build:b"]);
+
+        // 'c' doesn't implement 'deploy'
+        let plan = project
+            .plan("deploy", &[String::from("c")])
+            .expect("failed to create plan");
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn plan_skip_run() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+
+        [steps.build]
+        [steps.cleanup]
+        skip_run = true
+        [steps.destroy]
+        depends_on = [\"build\", \"cleanup\"]
+        skip_run = true
+        [steps.deploy]
+        depends_on = [\"destroy\"]
+        ",
+        );
+        let folder = project_dir.path();
+        create_service_with_config(
+            folder,
+            "a",
+            "
+        [steps.build]
+        [steps.cleanup]
+        [steps.destroy]
+        [steps.deploy]
+        ",
+        );
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let services = [String::from("a")];
+        let plan_names = |step_name: &str| {
+            project
+                .plan(step_name, &services)
+                .expect("failed to create plan")
+                .steps()
+                .map(|step| step.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // 'destroy' is only included when requested explicitly, but its
+        // dependencies are kept
+        assert_eq!(plan_names("deploy"), ["build:a", "deploy:a"]);
+        assert_eq!(
+            project
+                .plan("deploy", &services)
+                .expect("failed to create plan")
+                .dependencies("deploy:a"),
+            ["build:a"]
+        );
+        // 'cleanup' is included as a dependency of a skipped step that is
+        // requested explicitly
+        assert_eq!(plan_names("destroy"), ["build:a", "cleanup:a", "destroy:a"]);
+        assert_eq!(plan_names("cleanup"), ["cleanup:a"]);
+    }
+
+    #[test]
+    fn plan_missing() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service(folder, "my-service");
+        let project = Project::from_path(folder).expect("failed to load the project");
+
+        match project.plan("my-other-step", &[String::from("my-service")]) {
+            Err(Error::MissingStep { name }) => assert_eq!(name, "my-other-step"),
+            _ => panic!("expected a missing step error"),
+        }
+        match project.plan("my-step", &[String::from("my-other-service")]) {
+            Err(Error::MissingService { name }) => assert_eq!(name, "my-other-service"),
+            _ => panic!("expected a missing service error"),
+        }
+    }
+
+    #[test]
+    fn changed_services() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service(folder, "a");
+        create_service(folder, "b/c");
+        create_service(folder, "d");
+        let repo = Repository::open(folder).expect("failed to open repository");
+        commit_all(&repo, "first");
+
+        std::fs::write(folder.join("README.md"), "readme").expect("failed to write file");
+        std::fs::write(folder.join(SERVICE_FOLDER).join("b/c/file.txt"), "file")
+            .expect("failed to write file");
+        create_dir_all(folder.join(SERVICE_FOLDER).join("d/src")).expect("failed to create folder");
+        std::fs::write(folder.join(SERVICE_FOLDER).join("d/src/main.rs"), "")
+            .expect("failed to write file");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let changed = project
+            .changed_services(&DiffBase::Rev(String::from("HEAD")), &DiffTarget::WorkTree)
+            .expect("failed to get changed services");
+
+        assert_eq!(changed.into_iter().collect::<Vec<_>>(), ["b/c", "d"]);
+    }
+
+    #[test]
+    fn changed_services_recipes() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_recipe(folder, "my-recipe");
+        create_recipe(folder, "my-other-recipe");
+        create_service_with_config(folder, "a", "recipes = [\"my-recipe\"]");
+        create_service_with_config(folder, "b", "recipes = [\"my-other-recipe\"]");
+        create_service_with_config(folder, "c", "");
+        let repo = Repository::open(folder).expect("failed to open repository");
+        commit_all(&repo, "first");
+
+        std::fs::write(folder.join(RECIPE_FOLDER).join("my-recipe.toml"), "")
+            .expect("failed to write file");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let changed = project
+            .changed_services(&DiffBase::Rev(String::from("HEAD")), &DiffTarget::WorkTree)
+            .expect("failed to get changed services");
+
+        assert_eq!(changed.into_iter().collect::<Vec<_>>(), ["a"]);
+    }
+
+    #[test]
+    fn changed_services_inputs() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service_with_config(folder, "a", "inputs = [\"lib/proto/**\"]");
+        create_service_with_config(folder, "b", "inputs = [\"lib/*.txt\"]");
+        create_service_with_config(folder, "c", "");
+        create_dir_all(folder.join("lib/proto")).expect("failed to create folder");
+        std::fs::write(folder.join("lib/proto/api.proto"), "").expect("failed to write file");
+        let repo = Repository::open(folder).expect("failed to open repository");
+        commit_all(&repo, "first");
+
+        std::fs::write(folder.join("lib/proto/api.proto"), "syntax").expect("failed to write file");
+        // Doesn't match 'lib/*.txt' as '*' doesn't match path separators
+        std::fs::write(folder.join("lib/proto/notes.txt"), "").expect("failed to write file");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let changed = project
+            .changed_services(&DiffBase::Rev(String::from("HEAD")), &DiffTarget::WorkTree)
+            .expect("failed to get changed services");
+
+        assert_eq!(changed.into_iter().collect::<Vec<_>>(), ["a"]);
+    }
+
+    #[test]
+    fn changed_services_invalid_input() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service_with_config(folder, "a", "inputs = [\"lib/[\"]");
+        let repo = Repository::open(folder).expect("failed to open repository");
+        commit_all(&repo, "first");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        match project.changed_services(&DiffBase::Rev(String::from("HEAD")), &DiffTarget::WorkTree)
+        {
+            Err(Error::InvalidInputPattern {
+                service, pattern, ..
+            }) => {
+                assert_eq!(service, "a");
+                assert_eq!(pattern, "lib/[");
+            }
+            _ => panic!("expected an invalid input pattern error"),
+        }
+    }
+
+    #[test]
+    fn changed_services_project_config() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_service(folder, "a");
+        create_service(folder, "b");
+        let repo = Repository::open(folder).expect("failed to open repository");
+        commit_all(&repo, "first");
+
+        let mut cfg_file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(folder.join(PROJECT_CONFIG_FILENAME))
+            .expect("failed to open the project config file");
+        cfg_file
+            .write_all(b"[steps.my-other-step]\n")
+            .expect("failed to write project config file");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let changed = project
+            .changed_services(&DiffBase::Rev(String::from("HEAD")), &DiffTarget::WorkTree)
+            .expect("failed to get changed services");
+        assert_eq!(changed.into_iter().collect::<Vec<_>>(), ["a", "b"]);
+
+        // Disable the option
+        cfg_file
+            .write_all(b"[options]\nconfig_changes_all = false\n")
+            .expect("failed to write project config file");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let changed = project
+            .changed_services(&DiffBase::Rev(String::from("HEAD")), &DiffTarget::WorkTree)
+            .expect("failed to get changed services");
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn service_step_graph() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+
+        [steps.build]
+        [steps.test]
+        depends_on = [\"build\"]
+        [steps.deploy]
+        depends_on = [\"test\"]
+        ",
+        );
+        let folder = project_dir.path();
+        create_service_with_config(
+            folder,
+            "a",
+            "
+        [steps.build]
+        [steps.deploy]
+        depends_on = [\"deploy:b\"]
+        ",
+        );
+        create_service_with_config(
+            folder,
+            "b",
+            "
+        [steps.build]
+        [steps.test]
+        [steps.deploy]
+        ",
+        );
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let graph = project
+            .service_step_graph()
+            .expect("failed to build the graph");
+
+        // 'a' doesn't implement 'test', so 'deploy:a' depends on 'build:a'
+        assert_eq!(
+            graph.dependencies("deploy:a").collect::<Vec<_>>(),
+            ["build:a", "deploy:b"]
+        );
+        assert_eq!(
+            graph.dependencies("deploy:b").collect::<Vec<_>>(),
+            ["test:b"]
+        );
+        assert_eq!(
+            graph.dependencies("test:b").collect::<Vec<_>>(),
+            ["build:b"]
+        );
+        assert_eq!(graph.dependencies("build:b").count(), 0);
+    }
+
+    #[test]
+    fn fingerprints() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+
+        [steps.build]
+
+        [steps.test]
+        depends_on = [\"build\"]
+
+        [steps.deploy]
+        depends_on = [\"test\"]
+        cache = false
+        ",
+        );
+        let folder = project_dir.path();
+        let service_config = "
+        [steps.build]
+        run = \"make\"
+
+        [steps.test]
+        run = \"make test\"
+
+        [steps.deploy]
+        run = \"make deploy\"
+        ";
+        create_service_with_config(folder, "a", service_config);
+        create_service_with_config(
+            folder,
+            "b",
+            &service_config.replace("make test\"", "make test\"\ndepends_on = [\"build:a\"]"),
+        );
+        let repo = Repository::open(folder).expect("failed to open repository");
+        commit_all(&repo, "first");
+
+        let fingerprints = || {
+            let project = Project::from_path(folder).expect("failed to load the project");
+            let plan = project
+                .plan("deploy", &[String::from("a"), String::from("b")])
+                .expect("failed to create plan");
+            project
+                .fingerprints(&plan)
+                .expect("failed to compute fingerprints")
+        };
+
+        let first = fingerprints();
+        let mut names: Vec<&str> = first.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["build:a", "build:b", "test:a", "test:b"]);
+        assert_ne!(first["build:a"], first["build:b"]);
+        assert_eq!(first, fingerprints());
+
+        // Untracked files change fingerprints, unless they are ignored
+        std::fs::write(folder.join(".gitignore"), "ignored.txt").expect("failed to write file");
+        std::fs::write(folder.join(SERVICE_FOLDER).join("a/ignored.txt"), "a")
+            .expect("failed to write file");
+        assert_eq!(first, fingerprints());
+        std::fs::write(folder.join(SERVICE_FOLDER).join("a/untracked.txt"), "a")
+            .expect("failed to write file");
+        let untracked = fingerprints();
+        assert_ne!(first["build:a"], untracked["build:a"]);
+        assert_eq!(first["build:b"], untracked["build:b"]);
+
+        // Changes to tracked files change the fingerprints of the service
+        // and of the step:service pairs depending on it
+        std::fs::write(folder.join(SERVICE_FOLDER).join("a/main.c"), "int main;")
+            .expect("failed to write file");
+        commit_all(&repo, "second");
+        let second = fingerprints();
+        assert_ne!(first["build:a"], second["build:a"]);
+        assert_ne!(first["test:a"], second["test:a"]);
+        assert_eq!(first["build:b"], second["build:b"]);
+        assert_ne!(first["test:b"], second["test:b"]);
+
+        // Scripts are part of the fingerprint
+        std::fs::write(
+            folder.join(SERVICE_FOLDER).join("a/orcs.toml"),
+            service_config.replace("make test", "make check"),
+        )
+        .expect("failed to write file");
+        let third = fingerprints();
+        assert_ne!(second["test:a"], third["test:a"]);
+        assert_eq!(second["build:b"], third["build:b"]);
+
+        // Environment variables are part of the fingerprint, even when they
+        // come from untracked env files
+        let project_config = std::fs::read_to_string(folder.join(PROJECT_CONFIG_FILENAME))
+            .expect("failed to read file");
+        std::fs::write(
+            folder.join(PROJECT_CONFIG_FILENAME),
+            project_config.replace(
+                "name = \"my-project\"",
+                "name = \"my-project\"\nenv_file = \".env\"",
+            ),
+        )
+        .expect("failed to write file");
+        std::fs::write(folder.join(".env"), "REGION=eu-west-1").expect("failed to write file");
+        let fourth = fingerprints();
+        std::fs::write(folder.join(".env"), "REGION=us-east-1").expect("failed to write file");
+        assert_ne!(fourth["build:b"], fingerprints()["build:b"]);
+
+        // Scripts are fingerprinted with their placeholders rendered
+        std::fs::write(
+            folder.join(SERVICE_FOLDER).join("b/orcs.toml"),
+            service_config.replace("run = \"make\"", "run = \"make {{ project.name }}\""),
+        )
+        .expect("failed to write file");
+        let fifth = fingerprints();
+        let project_config = std::fs::read_to_string(folder.join(PROJECT_CONFIG_FILENAME))
+            .expect("failed to read file");
+        std::fs::write(
+            folder.join(PROJECT_CONFIG_FILENAME),
+            project_config.replace("name = \"my-project\"", "name = \"my-other-project\""),
+        )
+        .expect("failed to write file");
+        let sixth = fingerprints();
+        assert_ne!(fifth["build:b"], sixth["build:b"]);
+        assert_eq!(fifth["build:a"], sixth["build:a"]);
+    }
+
+    #[test]
+    fn fingerprints_recipes() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_recipe(folder, "my-recipe");
+        create_service_with_config(folder, "a", "recipes = [\"my-recipe\"]");
+        create_service_with_config(folder, "b", "[steps.my-step]\nrun = \"true\"");
+        let repo = Repository::open(folder).expect("failed to open repository");
+        commit_all(&repo, "first");
+
+        let fingerprints = || {
+            let project = Project::from_path(folder).expect("failed to load the project");
+            let plan = project
+                .plan("my-step", &[String::from("a"), String::from("b")])
+                .expect("failed to create plan");
+            project
+                .fingerprints(&plan)
+                .expect("failed to compute fingerprints")
+        };
+
+        let first = fingerprints();
+        std::fs::write(
+            folder.join(RECIPE_FOLDER).join("my-recipe.toml"),
+            "[steps.my-step]\nrun = \"my-run-script\"\n# comment",
+        )
+        .expect("failed to write file");
+        let second = fingerprints();
+
+        assert_ne!(first["my-step:a"], second["my-step:a"]);
+        assert_eq!(first["my-step:b"], second["my-step:b"]);
+    }
+
+    #[test]
+    fn env_files() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+        env_file = \"project.env\"
+        env = { A = \"project\" }
+
+        [steps.my-step]
+        ",
+        );
+        let folder = project_dir.path();
+        std::fs::write(
+            folder.join("project.env"),
+            "A=project-file\nB=project-file\n",
+        )
+        .expect("failed to write file");
+        create_service_with_config(
+            folder,
+            "my-service",
+            "
+        recipes = [\"my-recipe\"]
+        env_file = \"service.env\"
+
+        [steps.my-step]
+        env = { D = \"step\" }
+        ",
+        );
+        std::fs::write(
+            folder.join(SERVICE_FOLDER).join("my-service/service.env"),
+            "C=service-file\nD=service-file\n",
+        )
+        .expect("failed to write file");
+        create_dir_all(folder.join(RECIPE_FOLDER)).expect("failed to create recipe folder");
+        std::fs::write(
+            folder.join(RECIPE_FOLDER).join("my-recipe.toml"),
+            "
+        [steps.my-step]
+        env_file = \"recipe.env\"
+        ",
+        )
+        .expect("failed to write file");
+        std::fs::write(
+            folder.join(RECIPE_FOLDER).join("recipe.env"),
+            "C=recipe-file\nE=recipe-file\n",
+        )
+        .expect("failed to write file");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let executor = crate::LocalExecutor::new(folder);
+        let project_env = project.env(&executor);
+        assert_eq!(project_env["A"], "project");
+        assert_eq!(project_env["B"], "project-file");
+        assert_eq!(project_env[PROJECT_VAR], "my-project");
+        assert_eq!(project_env[ROOT_VAR], folder.to_string_lossy());
+
+        let service = project
+            .get_service("my-service")
+            .expect("failed to get service");
+        let step = service.get_step("my-step").expect("failed to get step");
+        let env = step.script_env(&project_env);
+        assert_eq!(env["A"], "project");
+        assert_eq!(env["C"], "service-file");
+        assert_eq!(env["D"], "step");
+        assert_eq!(env["E"], "recipe-file");
+
+        // Missing env files are reported
+        std::fs::remove_file(folder.join(RECIPE_FOLDER).join("recipe.env"))
+            .expect("failed to remove file");
+        let project = Project::from_path(folder).expect("failed to load the project");
+        match project.validate() {
+            Err(Error::CannotReadEnvFile { .. }) => (),
+            _ => panic!("expected a cannot read env file error"),
+        }
+    }
+
+    #[test]
+    fn plan_templates() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+        env = { REGION = \"eu-west-1\" }
+
+        [steps.my-step]
+        ",
+        );
+        let folder = project_dir.path();
+        create_service_with_config(
+            folder,
+            "a",
+            "
+        recipes = [\"my-recipe\"]
+        ",
+        );
+        create_service_with_config(
+            folder,
+            "b",
+            "
+        [steps.my-step]
+        run = \"echo {{ env.MISSING }}\"
+        ",
+        );
+        create_dir_all(folder.join(RECIPE_FOLDER)).expect("failed to create recipe folder");
+        std::fs::write(
+            folder.join(RECIPE_FOLDER).join("my-recipe.toml"),
+            "
+        [steps.my-step]
+        check = true
+        run = \"deploy {{ project.name }}/{{ service.name }} --step {{step.name}} --region {{ env.REGION }}\"
+        ",
+        )
+        .expect("failed to write file");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let plan = project
+            .plan("my-step", &[String::from("a")])
+            .expect("failed to create plan");
+        let step = plan.steps().next().expect("missing step");
+        assert_eq!(step.check(), &Script::Override(true));
+        assert_eq!(
+            step.run(),
+            &Script::Script(String::from(
+                "deploy my-project/a --step my-step --region eu-west-1"
+            ))
+        );
+
+        match project.plan("my-step", &[String::from("b")]) {
+            Err(Error::UndefinedVariable { name, variable }) => {
+                assert_eq!(name, "my-step:b");
+                assert_eq!(variable, "env.MISSING");
+            }
+            _ => panic!("expected an undefined variable error"),
+        }
+    }
+
+    #[test]
+    fn recipe_params() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        create_dir_all(folder.join(RECIPE_FOLDER)).expect("failed to create recipe folder");
+        std::fs::write(
+            folder.join(RECIPE_FOLDER).join("docker.toml"),
+            "
+        [params.dockerfile]
+        default = \"Dockerfile\"
+
+        [params.registry]
+        required = true
+
+        [steps.my-step]
+        run = [
+            \"docker build -f {{ params.dockerfile }} -t {{ params.registry }}/{{ service.name }} .\",
+            \"docker push {{ params.registry }}/{{ service.name }}\",
+        ]
+        ",
+        )
+        .expect("failed to write file");
+        let service = |name: &str, recipe: &str| {
+            create_service_with_config(folder, name, &format!("recipes = [{}]", recipe));
+        };
+        service(
+            "a",
+            "{ name = \"docker\", with = { registry = \"example.com\", dockerfile = \"Dockerfile.prod\" } }",
+        );
+        service(
+            "b",
+            "{ name = \"docker\", with = { registry = \"example.com\" } }",
+        );
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let plan = project
+            .plan("my-step", &[String::from("a"), String::from("b")])
+            .expect("failed to create plan");
+        let scripts: Vec<&Script> = plan.steps().map(|step| step.run()).collect();
+        assert_eq!(
+            scripts,
+            [
+                &Script::Script(String::from(
+                    "docker build -f Dockerfile.prod -t example.com/a .\ndocker push example.com/a"
+                )),
+                &Script::Script(String::from(
+                    "docker build -f Dockerfile -t example.com/b .\ndocker push example.com/b"
+                )),
+            ]
+        );
+
+        // Required parameters must be set
+        service("b", "\"docker\"");
+        let project = Project::from_path(folder).expect("failed to load the project");
+        match project.validate() {
+            Err(Error::MissingRecipeParam {
+                service,
+                recipe,
+                param,
+            }) => {
+                assert_eq!(service, "b");
+                assert_eq!(recipe, "docker");
+                assert_eq!(param, "registry");
+            }
+            _ => panic!("expected a missing recipe param error"),
+        }
+
+        // Arguments must match parameters
+        service(
+            "b",
+            "{ name = \"docker\", with = { registry = \"example.com\", tag = \"latest\" } }",
+        );
+        let project = Project::from_path(folder).expect("failed to load the project");
+        match project.validate() {
+            Err(Error::UnknownRecipeParam { param, .. }) => assert_eq!(param, "tag"),
+            _ => panic!("expected an unknown recipe param error"),
+        }
+    }
+
+    #[test]
+    fn recipe_extends() {
+        let project_dir = create_project();
+        let folder = project_dir.path();
+        let recipe = |name: &str, data: &str| {
+            create_dir_all(folder.join(RECIPE_FOLDER)).expect("failed to create recipe folder");
+            std::fs::write(
+                folder.join(RECIPE_FOLDER).join(format!("{}.toml", name)),
+                data,
+            )
+            .expect("failed to write file");
+        };
+        recipe(
+            "rust",
+            "
+        [params.profile]
+        default = \"release\"
+
+        [steps.my-step]
+        check = \"cargo check\"
+        run = \"cargo build --profile {{ params.profile }}\"
+        container_image = \"rust\"
+        env = { A = \"rust\", B = \"rust\" }
+        ",
+        );
+        recipe(
+            "zip",
+            "
+        [steps.my-step]
+        run = \"zip\"
+        outputs = [\"zip\"]
+        ",
+        );
+        recipe(
+            "rust-lambda",
+            "
+        extends = [\"zip\", \"rust\"]
+
+        [steps.my-step]
+        check = \"cargo lambda check\"
+        env = { A = \"rust-lambda\" }
+        ",
+        );
+        create_service_with_config(folder, "a", "recipes = [\"rust-lambda\"]");
+        create_service_with_config(folder, "b", "recipes = [\"rust-lambda\", \"rust\"]");
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let service = project.get_service("b").expect("failed to get service");
+        assert_eq!(service.recipes(), ["rust-lambda", "rust", "zip"]);
+        let service = project.get_service("a").expect("failed to get service");
+        assert_eq!(service.recipes(), ["rust-lambda", "rust", "zip"]);
+
+        let step = service.get_step("my-step").expect("failed to get step");
+        assert_eq!(
+            step.check(),
+            &Script::Script(String::from("cargo lambda check"))
+        );
+        assert_eq!(
+            step.run(),
+            &Script::Script(String::from("cargo build --profile release"))
+        );
+        assert_eq!(step.container_image(), Some("rust"));
+        assert_eq!(step.outputs(), ["zip"]);
+        assert_eq!(step.env()["A"], "rust-lambda");
+        assert_eq!(step.env()["B"], "rust");
+
+        // Values keep track of the recipe they come from
+        let source = |name: &str| crate::Source::Recipe(name.to_string());
+        assert_eq!(step.sources().check, source("rust-lambda"));
+        assert_eq!(step.sources().run, source("rust"));
+        assert_eq!(step.sources().outputs, source("zip"));
+
+        // Cycles are detected
+        recipe("rust", "extends = [\"rust-lambda\"]");
+        let project = Project::from_path(folder).expect("failed to load the project");
+        match project.validate() {
+            Err(Error::RecipeCycle { path }) => {
+                assert_eq!(path, ["rust-lambda", "rust", "rust-lambda"])
+            }
+            _ => panic!("expected a recipe cycle error"),
+        }
+    }
+
+    #[test]
+    fn plan_recipe_depends_on() {
+        let project_dir = create_project_with_config(
+            "
+        name = \"my-project\"
+
+        [steps.build]
+
+        [steps.deploy]
+        ",
+        );
+        let folder = project_dir.path();
+        create_dir_all(folder.join(RECIPE_FOLDER)).expect("failed to create recipe folder");
+        std::fs::write(
+            folder.join(RECIPE_FOLDER).join("lambda.toml"),
+            "
+        [steps.build]
+        run = \"make\"
+
+        [steps.deploy]
+        depends_on = [\"build:self\"]
+        run = \"make deploy\"
+        ",
+        )
+        .expect("failed to write file");
+        create_service_with_config(folder, "a", "recipes = [\"lambda\"]");
+        create_service_with_config(
+            folder,
+            "b",
+            "
+        recipes = [\"lambda\"]
+
+        [steps.deploy]
+        depends_on = [\"deploy:a\"]
+        ",
+        );
+
+        let project = Project::from_path(folder).expect("failed to load the project");
+        let plan = project
+            .plan("deploy", &[String::from("b")])
+            .expect("failed to create plan");
+
+        assert_eq!(plan.dependencies("deploy:a"), ["build:a"]);
+        assert_eq!(plan.dependencies("deploy:b"), ["build:b", "deploy:a"]);
     }
 }
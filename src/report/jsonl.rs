@@ -0,0 +1,95 @@
+use super::{Event, Reporter};
+use crate::{Error, Result};
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Reporter writing events as JSON Lines
+///
+/// Each event is written as a single JSON object on its own line, with the
+/// event type in the `event` field.
+pub struct JsonLinesReporter<W> {
+    writer: Mutex<W>,
+}
+
+impl<W> JsonLinesReporter<W>
+where
+    W: Write + Send,
+{
+    /// Create a new reporter writing to `writer`
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Retrieve the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner().expect("failed to retrieve writer")
+    }
+}
+
+impl<W> Reporter for JsonLinesReporter<W>
+where
+    W: Write + Send,
+{
+    fn report(&self, event: &Event) -> Result<()> {
+        let mut line = serde_json::to_vec(event).expect("failed to serialize event");
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock().expect("failed to lock writer");
+        writer
+            .write_all(&line)
+            .and_then(|_| writer.flush())
+            .map_err(|source| Error::CannotWriteReport { source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{OutputStream, ScriptKind};
+    use crate::StepStatus;
+
+    #[test]
+    fn report() {
+        let reporter = JsonLinesReporter::new(Vec::new());
+
+        let events = [
+            Event::PlanCreated {
+                layers: vec![vec!["build:a"]],
+            },
+            Event::StepStarted { name: "build:a" },
+            Event::Output {
+                name: "build:a",
+                script: ScriptKind::Run,
+                stream: OutputStream::Stdout,
+                data: "hello\n",
+            },
+            Event::StepFinished {
+                name: "build:a",
+                status: StepStatus::Succeeded,
+                exit_code: Some(0),
+                duration_ms: 12,
+            },
+            Event::RunFinished {
+                success: true,
+                duration_ms: 15,
+            },
+        ];
+        for event in events.iter() {
+            reporter.report(event).expect("failed to report event");
+        }
+
+        let data = String::from_utf8(reporter.into_inner()).expect("invalid UTF-8");
+        assert_eq!(
+            data.lines().collect::<Vec<_>>(),
+            [
+                r#"{"event":"plan_created","layers":[["build:a"]]}"#,
+                r#"{"event":"step_started","name":"build:a"}"#,
+                r#"{"event":"output","name":"build:a","script":"run","stream":"stdout","data":"hello\n"}"#,
+                r#"{"event":"step_finished","name":"build:a","status":"succeeded","exit_code":0,"duration_ms":12}"#,
+                r#"{"event":"run_finished","success":true,"duration_ms":15}"#,
+            ]
+        );
+    }
+}
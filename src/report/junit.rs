@@ -0,0 +1,348 @@
+use super::{Event, OutputStream, Reporter};
+use crate::{Error, Result, StepStatus};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Reporter collecting results into a JUnit XML report
+///
+/// Each step:service pair is a testcase, grouped into a testsuite per step.
+/// Captured output is only attached to failed testcases. Step:service pairs
+/// from the plan that did not run are reported as skipped.
+#[derive(Default)]
+pub struct JUnitReporter {
+    state: Mutex<JUnitState>,
+}
+
+#[derive(Default)]
+struct JUnitState {
+    /// Testcase names for each testsuite, in plan order
+    suites: Vec<(String, Vec<String>)>,
+    cases: HashMap<String, TestCase>,
+}
+
+#[derive(Default)]
+struct TestCase {
+    /// Final status, if the step:service pair completed
+    status: Option<StepStatus>,
+    exit_code: Option<i32>,
+    duration_ms: u128,
+    stdout: String,
+    stderr: String,
+}
+
+impl JUnitReporter {
+    /// Create a new reporter
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Write the report for the events received so far
+    pub fn write<W>(&self, mut writer: W) -> Result<()>
+    where
+        W: Write,
+    {
+        writer
+            .write_all(self.to_xml().as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(|source| Error::CannotWriteReport { source })
+    }
+
+    /// Render the report as an XML document
+    fn to_xml(&self) -> String {
+        let state = self.state.lock().expect("failed to lock report");
+
+        let mut suites = String::new();
+        let mut totals = Counts::default();
+        for (suite_name, case_names) in &state.suites {
+            let mut cases = String::new();
+            let mut counts = Counts::default();
+            for case_name in case_names {
+                let case = &state.cases[case_name];
+                counts.add(case);
+                write_case(&mut cases, suite_name, case_name, case);
+            }
+
+            writeln!(
+                suites,
+                r#"  <testsuite name="{}" {}>"#,
+                escape(suite_name),
+                counts
+            )
+            .expect("failed to write to string");
+            suites.push_str(&cases);
+            suites.push_str("  </testsuite>\n");
+            totals.merge(&counts);
+        }
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        writeln!(xml, r#"<testsuites name="orcs" {}>"#, totals).expect("failed to write to string");
+        xml.push_str(&suites);
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+}
+
+impl Reporter for JUnitReporter {
+    fn report(&self, event: &Event) -> Result<()> {
+        let mut state = self.state.lock().expect("failed to lock report");
+
+        match event {
+            Event::PlanCreated { layers } => {
+                for name in layers.iter().flatten() {
+                    let suite_name = suite_name(name);
+                    match state.suites.iter_mut().find(|(s, _)| s == suite_name) {
+                        Some((_, case_names)) => case_names.push(name.to_string()),
+                        None => state
+                            .suites
+                            .push((suite_name.to_string(), vec![name.to_string()])),
+                    }
+                    state.cases.insert(name.to_string(), Default::default());
+                }
+            }
+            Event::Output {
+                name, stream, data, ..
+            } => {
+                let case = state.cases.entry(name.to_string()).or_default();
+                match stream {
+                    OutputStream::Stdout => case.stdout.push_str(data),
+                    OutputStream::Stderr => case.stderr.push_str(data),
+                }
+            }
+            Event::StepFinished {
+                name,
+                status,
+                exit_code,
+                duration_ms,
+            } => {
+                let case = state.cases.entry(name.to_string()).or_default();
+                case.status = Some(*status);
+                case.exit_code = *exit_code;
+                case.duration_ms = *duration_ms;
+            }
+            Event::StepStarted { .. } | Event::RunFinished { .. } => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Testcase counts for a testsuite
+#[derive(Default)]
+struct Counts {
+    tests: usize,
+    failures: usize,
+    skipped: usize,
+    duration_ms: u128,
+}
+
+impl Counts {
+    fn add(&mut self, case: &TestCase) {
+        self.tests += 1;
+        match case.status {
+            Some(StepStatus::Failed) => self.failures += 1,
+            Some(StepStatus::Skipped) | None => self.skipped += 1,
+            _ => (),
+        }
+        self.duration_ms += case.duration_ms;
+    }
+
+    fn merge(&mut self, other: &Counts) {
+        self.tests += other.tests;
+        self.failures += other.failures;
+        self.skipped += other.skipped;
+        self.duration_ms += other.duration_ms;
+    }
+}
+
+impl std::fmt::Display for Counts {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            r#"tests="{}" failures="{}" errors="0" skipped="{}" time="{}""#,
+            self.tests,
+            self.failures,
+            self.skipped,
+            seconds(self.duration_ms)
+        )
+    }
+}
+
+fn write_case(xml: &mut String, suite_name: &str, case_name: &str, case: &TestCase) {
+    write!(
+        xml,
+        r#"    <testcase name="{}" classname="{}" time="{}""#,
+        escape(case_name),
+        escape(suite_name),
+        seconds(case.duration_ms)
+    )
+    .expect("failed to write to string");
+
+    match case.status {
+        Some(StepStatus::Failed) => {
+            let message = match case.exit_code {
+                Some(code) => format!("script failed with exit code {}", code),
+                None => String::from("script terminated by a signal"),
+            };
+            xml.push_str(">\n");
+            writeln!(xml, r#"      <failure message="{}"/>"#, escape(&message))
+                .expect("failed to write to string");
+            if !case.stdout.is_empty() {
+                writeln!(
+                    xml,
+                    "      <system-out>{}</system-out>",
+                    escape(&case.stdout)
+                )
+                .expect("failed to write to string");
+            }
+            if !case.stderr.is_empty() {
+                writeln!(
+                    xml,
+                    "      <system-err>{}</system-err>",
+                    escape(&case.stderr)
+                )
+                .expect("failed to write to string");
+            }
+            xml.push_str("    </testcase>\n");
+        }
+        Some(StepStatus::Skipped) => {
+            xml.push_str(">\n      <skipped/>\n    </testcase>\n");
+        }
+        None => {
+            xml.push_str(
+                ">\n      <skipped message=\"not run due to failures\"/>\n    </testcase>\n",
+            );
+        }
+        Some(StepStatus::UpToDate) | Some(StepStatus::Succeeded) | Some(StepStatus::Cached) => {
+            xml.push_str("/>\n")
+        }
+    }
+}
+
+/// Name of the testsuite for a step:service pair
+fn suite_name(name: &str) -> &str {
+    name.split(':').next().unwrap_or(name)
+}
+
+/// Format a duration in milliseconds as seconds
+fn seconds(duration_ms: u128) -> String {
+    format!("{:.3}", duration_ms as f64 / 1000.0)
+}
+
+/// Escape text for XML attributes and content
+///
+/// Characters that are not allowed in XML documents are removed.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::ScriptKind;
+
+    #[test]
+    fn escape_special_characters() {
+        assert_eq!(
+            escape("<a href=\"x\">&'\u{1b}[0m</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&apos;[0m&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn report() {
+        let reporter = JUnitReporter::new();
+
+        let events = [
+            Event::PlanCreated {
+                layers: vec![vec!["build:a", "build:b"], vec!["test:a"], vec!["deploy:a"]],
+            },
+            Event::StepStarted { name: "build:a" },
+            Event::StepFinished {
+                name: "build:a",
+                status: StepStatus::Succeeded,
+                exit_code: Some(0),
+                duration_ms: 1500,
+            },
+            Event::StepStarted { name: "build:b" },
+            Event::StepFinished {
+                name: "build:b",
+                status: StepStatus::Skipped,
+                exit_code: None,
+                duration_ms: 0,
+            },
+            Event::StepStarted { name: "test:a" },
+            Event::Output {
+                name: "test:a",
+                script: ScriptKind::Run,
+                stream: OutputStream::Stdout,
+                data: "1 < 2\n",
+            },
+            Event::Output {
+                name: "test:a",
+                script: ScriptKind::Run,
+                stream: OutputStream::Stderr,
+                data: "oops\n",
+            },
+            Event::StepFinished {
+                name: "test:a",
+                status: StepStatus::Failed,
+                exit_code: Some(3),
+                duration_ms: 250,
+            },
+            Event::RunFinished {
+                success: false,
+                duration_ms: 1800,
+            },
+        ];
+        for event in events.iter() {
+            reporter.report(event).expect("failed to report event");
+        }
+
+        let mut data = Vec::new();
+        reporter.write(&mut data).expect("failed to write report");
+        let data = String::from_utf8(data).expect("invalid UTF-8");
+
+        assert_eq!(
+            data,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="orcs" tests="4" failures="1" errors="0" skipped="2" time="1.750">
+  <testsuite name="build" tests="2" failures="0" errors="0" skipped="1" time="1.500">
+    <testcase name="build:a" classname="build" time="1.500"/>
+    <testcase name="build:b" classname="build" time="0.000">
+      <skipped/>
+    </testcase>
+  </testsuite>
+  <testsuite name="test" tests="1" failures="1" errors="0" skipped="0" time="0.250">
+    <testcase name="test:a" classname="test" time="0.250">
+      <failure message="script failed with exit code 3"/>
+      <system-out>1 &lt; 2
+</system-out>
+      <system-err>oops
+</system-err>
+    </testcase>
+  </testsuite>
+  <testsuite name="deploy" tests="1" failures="0" errors="0" skipped="1" time="0.000">
+    <testcase name="deploy:a" classname="deploy" time="0.000">
+      <skipped message="not run due to failures"/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
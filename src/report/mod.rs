@@ -0,0 +1,63 @@
+mod jsonl;
+mod junit;
+
+use crate::{Result, StepStatus};
+use serde::Serialize;
+
+pub use jsonl::JsonLinesReporter;
+pub use junit::JUnitReporter;
+
+/// Event emitted while running a `Plan`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The plan is about to run
+    ///
+    /// This contains the names of the step:service pairs for each layer.
+    PlanCreated { layers: Vec<Vec<&'a str>> },
+    /// A step:service pair started
+    StepStarted { name: &'a str },
+    /// Output captured from a check or run script
+    ///
+    /// This is emitted once per line, while the script is running.
+    Output {
+        name: &'a str,
+        script: ScriptKind,
+        stream: OutputStream,
+        data: &'a str,
+    },
+    /// A step:service pair finished
+    StepFinished {
+        name: &'a str,
+        status: StepStatus,
+        exit_code: Option<i32>,
+        duration_ms: u128,
+    },
+    /// All step:service pairs finished
+    RunFinished { success: bool, duration_ms: u128 },
+}
+
+/// Script that produced an output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptKind {
+    Check,
+    Run,
+}
+
+/// Output stream of a script
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Receiver for the events of a run
+///
+/// Reporters are shared between worker threads when running step:service
+/// pairs concurrently.
+pub trait Reporter: Sync {
+    /// Handle an event
+    fn report(&self, event: &Event) -> Result<()>;
+}
@@ -0,0 +1,345 @@
+use crate::report::{OutputStream, ScriptKind};
+use crate::{config::StepOnChanged, Executor, Result, Script, ScriptOutput, ServiceStep};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Exit code for a check script when the step is up to date
+const CHECK_UP_TO_DATE: i32 = 0;
+/// Exit code for a check script when the step needs to run
+const CHECK_NEEDS_RUN: i32 = 1;
+
+/// Callback receiving the output of the scripts of a `ServiceStep` as it
+/// arrives
+pub type ScriptOutputFn<'a> = dyn FnMut(ScriptKind, OutputStream, &[u8]) -> Result<()> + 'a;
+
+/// Status of a check for a `ServiceStep`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// Nothing needs to be done
+    UpToDate,
+    /// The run script needs to be executed
+    NeedsRun,
+    /// The check script failed
+    Failed,
+}
+
+/// Final status of a `ServiceStep`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    /// The step wasn't run, either because of the `on_changed` policy or
+    /// because there is nothing to run
+    Skipped,
+    /// The check reported that nothing needs to be done
+    UpToDate,
+    /// The run completed successfully
+    Succeeded,
+    /// A previous run with the same fingerprint completed successfully
+    Cached,
+    /// Either the check or the run failed
+    Failed,
+}
+
+/// Result of running a `ServiceStep`
+#[derive(Clone, Debug)]
+pub struct StepResult {
+    /// Name of the pair in 'step:service' format.
+    pub name: String,
+
+    /// Final status
+    pub status: StepStatus,
+
+    /// Output of the check script, if it was executed
+    pub check: Option<ScriptOutput>,
+
+    /// Output of the run script, if it was executed
+    pub run: Option<ScriptOutput>,
+
+    /// Key/value outputs produced by the scripts
+    pub values: BTreeMap<String, String>,
+}
+
+impl StepResult {
+    /// Whether the step completed without failure
+    pub fn success(&self) -> bool {
+        self.status != StepStatus::Failed
+    }
+
+    /// Total time spent running the check and run scripts
+    pub fn duration(&self) -> Duration {
+        self.outputs().map(|output| output.duration).sum()
+    }
+
+    /// Exit code of the last script executed, if any
+    pub fn exit_code(&self) -> Option<i32> {
+        self.run
+            .as_ref()
+            .or(self.check.as_ref())
+            .and_then(|output| output.code)
+    }
+
+    /// Outputs of the scripts executed, in execution order
+    pub fn outputs(&self) -> impl Iterator<Item = &ScriptOutput> {
+        self.check.iter().chain(self.run.iter())
+    }
+}
+
+/// Run `ServiceStep`s through an `Executor`
+pub struct Runner<'a> {
+    executor: &'a dyn Executor,
+}
+
+impl<'a> Runner<'a> {
+    /// Create a new runner using the given executor
+    pub fn new(executor: &'a dyn Executor) -> Self {
+        Self { executor }
+    }
+
+    /// Check if a `ServiceStep` needs to run
+    ///
+    /// Check scripts follow this exit code convention:
+    /// * `0` means that the step is up to date and doesn't need to run,
+    /// * `1` means that the step needs to run,
+    /// * any other exit code means that the check itself failed.
+    ///
+    /// `Script::Override` values short-circuit the check script, and a step
+    /// without a check script always needs to run.
+    ///
+    /// Scripts receive the variables in `env`.
+    pub fn check(
+        &self,
+        step: &ServiceStep,
+        env: &BTreeMap<String, String>,
+    ) -> Result<(CheckStatus, Option<ScriptOutput>)> {
+        self.check_with_output(step, env, &mut |_, _| Ok(()))
+    }
+
+    /// Check if a `ServiceStep` needs to run, passing the output of the
+    /// check script to `on_output` as it arrives
+    pub fn check_with_output(
+        &self,
+        step: &ServiceStep,
+        env: &BTreeMap<String, String>,
+        on_output: &mut dyn FnMut(OutputStream, &[u8]) -> Result<()>,
+    ) -> Result<(CheckStatus, Option<ScriptOutput>)> {
+        match step.check() {
+            Script::Override(true) | Script::None => Ok((CheckStatus::NeedsRun, None)),
+            Script::Override(false) => Ok((CheckStatus::UpToDate, None)),
+            Script::Script(body) => {
+                let output = self
+                    .executor
+                    .execute_with_output(step, body, env, on_output)?;
+                let status = match output.code {
+                    Some(CHECK_UP_TO_DATE) => CheckStatus::UpToDate,
+                    Some(CHECK_NEEDS_RUN) => CheckStatus::NeedsRun,
+                    _ => CheckStatus::Failed,
+                };
+                Ok((status, Some(output)))
+            }
+        }
+    }
+
+    /// Run a `ServiceStep` according to its `on_changed` policy
+    ///
+    /// With `StepOnChanged::CheckFirst`, the run script is only executed if
+    /// the check reports that the step needs to run.
+    pub fn run(
+        &self,
+        step: &ServiceStep,
+        on_changed: &StepOnChanged,
+        env: &BTreeMap<String, String>,
+    ) -> Result<StepResult> {
+        self.run_with_output(step, on_changed, env, &mut |_, _, _| Ok(()))
+    }
+
+    /// Run a `ServiceStep` according to its `on_changed` policy, passing
+    /// the output of its scripts to `on_output` as it arrives
+    pub fn run_with_output(
+        &self,
+        step: &ServiceStep,
+        on_changed: &StepOnChanged,
+        env: &BTreeMap<String, String>,
+        on_output: &mut ScriptOutputFn,
+    ) -> Result<StepResult> {
+        let mut result = StepResult {
+            name: step.name.clone(),
+            status: StepStatus::Skipped,
+            check: None,
+            run: None,
+            values: Default::default(),
+        };
+
+        match on_changed {
+            StepOnChanged::Skip => return Ok(result),
+            StepOnChanged::CheckFirst => {
+                let (status, output) = self.check_with_output(step, env, &mut |stream, data| {
+                    on_output(ScriptKind::Check, stream, data)
+                })?;
+                result.check = output;
+                match status {
+                    CheckStatus::NeedsRun => (),
+                    CheckStatus::UpToDate => {
+                        result.status = StepStatus::UpToDate;
+                        return Ok(result);
+                    }
+                    CheckStatus::Failed => {
+                        result.status = StepStatus::Failed;
+                        return Ok(result);
+                    }
+                }
+            }
+            StepOnChanged::Run => (),
+        }
+
+        result.status = match step.run() {
+            Script::Override(true) => StepStatus::Succeeded,
+            Script::Override(false) | Script::None => StepStatus::Skipped,
+            Script::Script(body) => {
+                let output =
+                    self.executor
+                        .execute_with_output(step, body, env, &mut |stream, data| {
+                            on_output(ScriptKind::Run, stream, data)
+                        })?;
+                let status = if output.success() {
+                    StepStatus::Succeeded
+                } else {
+                    StepStatus::Failed
+                };
+                result.run = Some(output);
+                status
+            }
+        };
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ScriptConfig, ServiceStepConfig};
+    use crate::executor::service_path;
+    use crate::test_utils::build_step;
+    use crate::LocalExecutor;
+    use std::fs::create_dir_all;
+    use tempfile::tempdir;
+
+    fn script(value: &str) -> ScriptConfig {
+        ScriptConfig::Multiline(value.to_string())
+    }
+
+    #[test]
+    fn check() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        create_dir_all(service_path(project_dir.path(), "my-service"))
+            .expect("failed to create service folder");
+        let executor = LocalExecutor::new(project_dir.path());
+        let runner = Runner::new(&executor);
+
+        let test_cases = [
+            (ScriptConfig::None, CheckStatus::NeedsRun),
+            (ScriptConfig::Boolean(true), CheckStatus::NeedsRun),
+            (ScriptConfig::Boolean(false), CheckStatus::UpToDate),
+            (script("exit 0"), CheckStatus::UpToDate),
+            (script("exit 1"), CheckStatus::NeedsRun),
+            (script("exit 2"), CheckStatus::Failed),
+        ];
+
+        for (check, value) in test_cases.iter().cloned() {
+            let step = build_step(
+                "my-service",
+                "my-step",
+                &ServiceStepConfig {
+                    check,
+                    ..Default::default()
+                },
+            );
+            let (status, _) = runner
+                .check(&step, &Default::default())
+                .expect("failed to run check");
+            assert_eq!(status, value);
+        }
+    }
+
+    #[test]
+    fn run_check_first() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        create_dir_all(service_path(project_dir.path(), "my-service"))
+            .expect("failed to create service folder");
+        let executor = LocalExecutor::new(project_dir.path());
+        let runner = Runner::new(&executor);
+
+        let test_cases = [
+            (script("exit 0"), StepStatus::UpToDate, false),
+            (script("exit 1"), StepStatus::Succeeded, true),
+            (script("exit 2"), StepStatus::Failed, false),
+            (ScriptConfig::Boolean(false), StepStatus::UpToDate, false),
+            (ScriptConfig::Boolean(true), StepStatus::Succeeded, true),
+        ];
+
+        for (check, value, has_run) in test_cases.iter().cloned() {
+            let step = build_step(
+                "my-service",
+                "my-step",
+                &ServiceStepConfig {
+                    check,
+                    run: script("echo run"),
+                    ..Default::default()
+                },
+            );
+            let result = runner
+                .run(&step, &StepOnChanged::CheckFirst, &Default::default())
+                .expect("failed to run step");
+            assert_eq!(result.status, value);
+            assert_eq!(result.run.is_some(), has_run);
+        }
+    }
+
+    #[test]
+    fn run_policies() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        create_dir_all(service_path(project_dir.path(), "my-service"))
+            .expect("failed to create service folder");
+        let executor = LocalExecutor::new(project_dir.path());
+        let runner = Runner::new(&executor);
+
+        // The check is never executed outside of `CheckFirst`
+        let step = build_step(
+            "my-service",
+            "my-step",
+            &ServiceStepConfig {
+                check: script("exit 0"),
+                run: script("echo run"),
+                ..Default::default()
+            },
+        );
+        let result = runner
+            .run(&step, &StepOnChanged::Run, &Default::default())
+            .expect("failed to run step");
+        assert_eq!(result.status, StepStatus::Succeeded);
+        assert!(result.check.is_none());
+        assert_eq!(result.run.expect("missing run output").stdout, b"run\n");
+
+        let result = runner
+            .run(&step, &StepOnChanged::Skip, &Default::default())
+            .expect("failed to run step");
+        assert_eq!(result.status, StepStatus::Skipped);
+        assert!(result.run.is_none());
+
+        // Failing run script
+        let step = build_step(
+            "my-service",
+            "my-step",
+            &ServiceStepConfig {
+                run: script("exit 1"),
+                ..Default::default()
+            },
+        );
+        let result = runner
+            .run(&step, &StepOnChanged::Run, &Default::default())
+            .expect("failed to run step");
+        assert_eq!(result.status, StepStatus::Failed);
+        assert!(!result.success());
+    }
+}
@@ -0,0 +1,851 @@
+use crate::artifacts::value_var;
+use crate::report::{Event, OutputStream, Reporter, ScriptKind};
+use crate::{
+    config::StepOnChanged, ArtifactStore, CacheBackend, CacheEntry, Error, Executor, Plan, Result,
+    Runner, ServiceStep, StepResult, StepStatus,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// Run the `ServiceStep`s of a `Plan` concurrently
+///
+/// Layers of the plan are run one after the other. Within a layer, up to
+/// `jobs` step:service pairs run at the same time.
+pub struct Scheduler<'a> {
+    executor: &'a dyn Executor,
+    runner: Runner<'a>,
+
+    /// Maximum number of concurrent step:service pairs
+    jobs: usize,
+
+    /// `on_changed` policy for each step
+    ///
+    /// Steps that are not present here use `StepOnChanged::Run`.
+    on_changed: HashMap<String, StepOnChanged>,
+
+    /// Reporters receiving the events of the run
+    reporters: Vec<&'a dyn Reporter>,
+
+    /// Caches for the results of successful step:service pairs, in lookup
+    /// order
+    caches: Vec<&'a dyn CacheBackend>,
+
+    /// Fingerprint of each cacheable step:service pair
+    fingerprints: HashMap<String, String>,
+
+    /// Storage for the outputs of step:service pairs
+    artifacts: Option<&'a ArtifactStore>,
+
+    /// Environment variables for the scripts of all step:service pairs
+    env: BTreeMap<String, String>,
+}
+
+impl<'a> Scheduler<'a> {
+    /// Create a new scheduler using the given executor
+    ///
+    /// By default, this runs one step:service pair at a time.
+    pub fn new(executor: &'a dyn Executor) -> Self {
+        Self {
+            executor,
+            runner: Runner::new(executor),
+            jobs: 1,
+            on_changed: Default::default(),
+            reporters: Default::default(),
+            caches: Default::default(),
+            fingerprints: Default::default(),
+            artifacts: None,
+            env: Default::default(),
+        }
+    }
+
+    /// Set the maximum number of concurrent step:service pairs
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Set the `on_changed` policy for a step
+    pub fn with_on_changed(mut self, step_name: &str, on_changed: StepOnChanged) -> Self {
+        self.on_changed.insert(step_name.to_string(), on_changed);
+        self
+    }
+
+    /// Add a reporter receiving the events of the run
+    pub fn with_reporter(mut self, reporter: &'a dyn Reporter) -> Self {
+        self.reporters.push(reporter);
+        self
+    }
+
+    /// Add a cache for the results of successful step:service pairs
+    ///
+    /// Caches are looked up in the order they were added. Results are
+    /// stored in all caches.
+    pub fn with_cache(mut self, cache: &'a dyn CacheBackend) -> Self {
+        self.caches.push(cache);
+        self
+    }
+
+    /// Set the fingerprints of the step:service pairs
+    ///
+    /// Only the pairs with a fingerprint are looked up and stored in the
+    /// caches.
+    pub fn with_fingerprints(mut self, fingerprints: HashMap<String, String>) -> Self {
+        self.fingerprints = fingerprints;
+        self
+    }
+
+    /// Collect the outputs of step:service pairs after successful runs
+    ///
+    /// Outputs are also stored in and restored from the caches. Key/value
+    /// outputs are passed as environment variables to the pairs that depend
+    /// on them.
+    pub fn with_artifacts(mut self, artifacts: &'a ArtifactStore) -> Self {
+        self.artifacts = Some(artifacts);
+        self
+    }
+
+    /// Set the environment variables for the scripts of all step:service
+    /// pairs
+    ///
+    /// The variables of each pair take precedence over these.
+    pub fn with_env(mut self, env: BTreeMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Run all the `ServiceStep`s of a plan
+    ///
+    /// This returns the results in plan order. If a step:service pair fails,
+    /// the current layer completes but the following layers are not run.
+    ///
+    /// The `RunFinished` event is sent even if the run stops with an error.
+    pub fn run(&self, plan: &Plan) -> Result<Vec<StepResult>> {
+        let start = Instant::now();
+        let mut results: Vec<StepResult> = Default::default();
+
+        self.report(&Event::PlanCreated {
+            layers: plan
+                .layers()
+                .iter()
+                .map(|layer| layer.iter().map(|step| step.name.as_str()).collect())
+                .collect(),
+        })?;
+
+        let run_result = self.run_layers(plan, &mut results);
+        let report_result = self.report(&Event::RunFinished {
+            success: run_result.is_ok()
+                && results.len() == plan.len()
+                && results.iter().all(StepResult::success),
+            duration_ms: start.elapsed().as_millis(),
+        });
+        run_result?;
+        report_result?;
+
+        Ok(results)
+    }
+
+    /// Run the layers of a plan, adding the results to `results`
+    fn run_layers(&self, plan: &Plan, results: &mut Vec<StepResult>) -> Result<()> {
+        let values: Mutex<HashMap<String, BTreeMap<String, String>>> = Default::default();
+
+        for layer in plan.layers() {
+            let next = AtomicUsize::new(0);
+            let layer_results: Mutex<Vec<Option<Result<StepResult>>>> =
+                Mutex::new(layer.iter().map(|_| None).collect());
+
+            thread::scope(|scope| {
+                for _ in 0..self.jobs.min(layer.len()) {
+                    scope.spawn(|| loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let step = match layer.get(index) {
+                            Some(step) => step,
+                            None => break,
+                        };
+                        let result = self
+                            .report(&Event::StepStarted { name: &step.name })
+                            .and_then(|_| self.run_step(step, self.step_env(plan, step, &values)))
+                            .and_then(|result| {
+                                self.report_result(&result)?;
+                                values
+                                    .lock()
+                                    .expect("failed to lock values")
+                                    .insert(result.name.clone(), result.values.clone());
+                                Ok(result)
+                            });
+                        layer_results.lock().expect("failed to lock results")[index] = Some(result);
+                    });
+                }
+            });
+
+            let layer_results = layer_results
+                .into_inner()
+                .expect("failed to retrieve results");
+            for result in layer_results {
+                results.push(result.expect("missing step result")?);
+            }
+
+            if results.iter().any(|result| !result.success()) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Environment variables for the scripts of a step:service pair
+    ///
+    /// This contains the variables of the pair on top of the scheduler's
+    /// variables, as well as the key/value outputs of its direct
+    /// dependencies.
+    fn step_env(
+        &self,
+        plan: &Plan,
+        step: &ServiceStep,
+        values: &Mutex<HashMap<String, BTreeMap<String, String>>>,
+    ) -> BTreeMap<String, String> {
+        let mut env = step.script_env(&self.env);
+        if let Some(artifacts) = self.artifacts {
+            env.extend(artifacts.env(self.executor, step));
+        }
+
+        let values = values.lock().expect("failed to lock values");
+        for dependency in plan.dependencies(&step.name) {
+            for (key, value) in values.get(dependency).into_iter().flatten() {
+                env.insert(value_var(dependency, key), value.clone());
+            }
+        }
+
+        env
+    }
+
+    /// Run a step:service pair, unless its result is already cached
+    ///
+    /// When a result is found in a cache, it is also stored in the caches
+    /// looked up before it.
+    fn run_step(&self, step: &ServiceStep, env: BTreeMap<String, String>) -> Result<StepResult> {
+        let fingerprint = self.fingerprints.get(&step.name);
+        let outputs = self.artifacts.map(|artifacts| artifacts.step_path(step));
+        let outputs = outputs.as_deref();
+
+        if let Some(fingerprint) = fingerprint {
+            for (index, cache) in self.caches.iter().enumerate() {
+                if let Some(entry) = cache.get(fingerprint, outputs)? {
+                    for cache in &self.caches[..index] {
+                        cache.put(fingerprint, &entry, outputs)?;
+                    }
+                    return Ok(StepResult {
+                        name: step.name.clone(),
+                        status: StepStatus::Cached,
+                        check: None,
+                        run: None,
+                        values: entry.values,
+                    });
+                }
+            }
+        }
+
+        let on_changed = self
+            .on_changed
+            .get(&step.step_name)
+            .copied()
+            .unwrap_or_default();
+        if let Some(artifacts) = self.artifacts {
+            artifacts.prepare_values(step)?;
+        }
+        let mut result =
+            self.runner
+                .run_with_output(step, &on_changed, &env, &mut |script, stream, data| {
+                    self.report_output(&step.name, script, stream, data)
+                })?;
+
+        // Outputs are only produced when the run script actually ran.
+        // Otherwise, the pair keeps the outputs of its previous run, and its
+        // result isn't cached, as there are no fresh outputs to store.
+        let mut cacheable = false;
+        if let (StepStatus::Succeeded, Some(output)) = (result.status, &mut result.run) {
+            cacheable = true;
+            if let Some(artifacts) = self.artifacts {
+                match artifacts.collect(step) {
+                    Ok(()) => artifacts.commit_values(step)?,
+                    // A missing output fails the pair, not the whole run
+                    Err(err @ Error::MissingOutput { .. }) => {
+                        let message = format!("{}\n", err);
+                        self.report_output(
+                            &step.name,
+                            ScriptKind::Run,
+                            OutputStream::Stderr,
+                            message.as_bytes(),
+                        )?;
+                        output.stderr.extend(message.as_bytes());
+                        result.status = StepStatus::Failed;
+                        cacheable = false;
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        if let StepStatus::Succeeded | StepStatus::UpToDate = result.status {
+            if let Some(artifacts) = self.artifacts {
+                result.values = artifacts.read_values(step)?;
+            }
+        }
+        if cacheable {
+            if let Some(fingerprint) = fingerprint {
+                let entry = CacheEntry {
+                    name: result.name.clone(),
+                    status: result.status,
+                    values: result.values.clone(),
+                };
+                for cache in &self.caches {
+                    cache.put(fingerprint, &entry, outputs)?;
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Send an event to all reporters
+    fn report(&self, event: &Event) -> Result<()> {
+        for reporter in &self.reporters {
+            reporter.report(event)?;
+        }
+        Ok(())
+    }
+
+    /// Send the output of a script of a step:service pair
+    fn report_output(
+        &self,
+        name: &str,
+        script: ScriptKind,
+        stream: OutputStream,
+        data: &[u8],
+    ) -> Result<()> {
+        self.report(&Event::Output {
+            name,
+            script,
+            stream,
+            data: &String::from_utf8_lossy(data),
+        })
+    }
+
+    /// Send the event for a completed step:service pair
+    ///
+    /// The output of its scripts is sent while they run.
+    fn report_result(&self, result: &StepResult) -> Result<()> {
+        self.report(&Event::StepFinished {
+            name: &result.name,
+            status: result.status,
+            exit_code: result.exit_code(),
+            duration_ms: result.duration().as_millis(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ScriptConfig, ServiceStepConfig};
+    use crate::executor::service_path;
+    use crate::report::JsonLinesReporter;
+    use crate::test_utils::{create_step, run_config};
+    use crate::{LocalCache, LocalExecutor, SharedCache};
+    use tempfile::tempdir;
+
+    #[test]
+    fn run_concurrently() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        // Each step waits for the other one to start, which can only succeed
+        // if both run at the same time.
+        let script = |own: &str, other: &str| {
+            format!(
+                "touch ../../{own}\nfor i in $(seq 50); do\n  [ -f ../../{other} ] && exit 0\n  sleep 0.1\ndone\nexit 1",
+                own = own,
+                other = other
+            )
+        };
+        let plan = Plan::new(vec![vec![
+            create_step(
+                root,
+                "a",
+                "my-step",
+                &run_config(&script("a.started", "b.started")),
+            ),
+            create_step(
+                root,
+                "b",
+                "my-step",
+                &run_config(&script("b.started", "a.started")),
+            ),
+        ]]);
+
+        let executor = LocalExecutor::new(root);
+        let scheduler = Scheduler::new(&executor).with_jobs(2);
+        let results = scheduler.run(&plan).expect("failed to run plan");
+
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (result.name.as_str(), result.status))
+                .collect::<Vec<_>>(),
+            [
+                ("my-step:a", StepStatus::Succeeded),
+                ("my-step:b", StepStatus::Succeeded)
+            ]
+        );
+    }
+
+    #[test]
+    fn run_layers() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let plan = Plan::new(vec![
+            vec![
+                create_step(root, "a", "my-step", &run_config("touch ../../a")),
+                create_step(root, "b", "my-step", &run_config("touch ../../b")),
+            ],
+            vec![create_step(
+                root,
+                "c",
+                "my-step",
+                &run_config("[ -f ../../a ] && [ -f ../../b ]"),
+            )],
+        ]);
+
+        let executor = LocalExecutor::new(root);
+        let scheduler = Scheduler::new(&executor).with_jobs(4);
+        let results = scheduler.run(&plan).expect("failed to run plan");
+
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(StepResult::success));
+    }
+
+    #[test]
+    fn run_stops_after_failure() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let plan = Plan::new(vec![
+            vec![
+                create_step(root, "a", "my-step", &run_config("exit 1")),
+                create_step(root, "b", "my-step", &run_config("true")),
+            ],
+            vec![create_step(root, "c", "my-step", &run_config("true"))],
+        ]);
+
+        let executor = LocalExecutor::new(root);
+        let scheduler = Scheduler::new(&executor).with_jobs(2);
+        let results = scheduler.run(&plan).expect("failed to run plan");
+
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (result.name.as_str(), result.status))
+                .collect::<Vec<_>>(),
+            [
+                ("my-step:a", StepStatus::Failed),
+                ("my-step:b", StepStatus::Succeeded)
+            ]
+        );
+    }
+
+    #[test]
+    fn run_on_changed() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let plan = Plan::new(vec![vec![create_step(
+            root,
+            "a",
+            "my-step",
+            &run_config("exit 1"),
+        )]]);
+
+        let executor = LocalExecutor::new(root);
+        let scheduler = Scheduler::new(&executor).with_on_changed("my-step", StepOnChanged::Skip);
+        let results = scheduler.run(&plan).expect("failed to run plan");
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].status, StepStatus::Skipped);
+    }
+
+    #[test]
+    fn run_reporter() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let plan = Plan::new(vec![
+            vec![create_step(root, "a", "my-step", &run_config("echo hello"))],
+            vec![create_step(
+                root,
+                "b",
+                "my-step",
+                &run_config("echo oops >&2\nexit 3"),
+            )],
+        ]);
+
+        let executor = LocalExecutor::new(root);
+        let reporter = JsonLinesReporter::new(Vec::new());
+        let scheduler = Scheduler::new(&executor).with_reporter(&reporter);
+        scheduler.run(&plan).expect("failed to run plan");
+
+        let data = String::from_utf8(reporter.into_inner()).expect("invalid UTF-8");
+        let events: Vec<serde_json::Value> = data
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("failed to parse event"))
+            .collect();
+
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event["event"].as_str().expect("missing event type"))
+                .collect::<Vec<_>>(),
+            [
+                "plan_created",
+                "step_started",
+                "output",
+                "step_finished",
+                "step_started",
+                "output",
+                "step_finished",
+                "run_finished"
+            ]
+        );
+        assert_eq!(
+            events[0]["layers"],
+            serde_json::json!([["my-step:a"], ["my-step:b"]])
+        );
+        assert_eq!(events[2]["stream"], "stdout");
+        assert_eq!(events[2]["data"], "hello\n");
+        assert_eq!(events[5]["stream"], "stderr");
+        assert_eq!(events[6]["status"], "failed");
+        assert_eq!(events[6]["exit_code"], 3);
+        assert_eq!(events[7]["success"], false);
+    }
+
+    #[test]
+    fn run_reporter_error() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let plan = Plan::new(vec![vec![create_step(
+            root,
+            "a",
+            "my-step",
+            &run_config("true"),
+        )]]);
+
+        let executor = LocalExecutor::new(root).with_shell(vec!["orcs-missing-shell"]);
+        let reporter = JsonLinesReporter::new(Vec::new());
+        let scheduler = Scheduler::new(&executor).with_reporter(&reporter);
+        match scheduler.run(&plan) {
+            Err(Error::CannotExecuteScript { name, .. }) => assert_eq!(name, "my-step:a"),
+            _ => panic!("expected a cannot execute script error"),
+        }
+
+        // The end of the run is reported even though it stopped with an error
+        let data = String::from_utf8(reporter.into_inner()).expect("invalid UTF-8");
+        let event: serde_json::Value =
+            serde_json::from_str(data.lines().last().expect("missing event"))
+                .expect("failed to parse event");
+        assert_eq!(event["event"], "run_finished");
+        assert_eq!(event["success"], false);
+    }
+
+    #[test]
+    fn run_cache() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let plan = Plan::new(vec![vec![
+            create_step(root, "a", "my-step", &run_config("echo run >> ../../a.log")),
+            create_step(root, "b", "my-step", &run_config("echo run >> ../../b.log")),
+        ]]);
+
+        let executor = LocalExecutor::new(root);
+        let cache = LocalCache::new(root.join("cache"));
+        let fingerprints: HashMap<String, String> =
+            vec![(String::from("my-step:a"), String::from("abcd"))]
+                .into_iter()
+                .collect();
+
+        for _ in 0..2 {
+            Scheduler::new(&executor)
+                .with_cache(&cache)
+                .with_fingerprints(fingerprints.clone())
+                .run(&plan)
+                .expect("failed to run plan");
+        }
+        let results = Scheduler::new(&executor)
+            .with_cache(&cache)
+            .with_fingerprints(fingerprints)
+            .run(&plan)
+            .expect("failed to run plan");
+
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (result.name.as_str(), result.status))
+                .collect::<Vec<_>>(),
+            [
+                ("my-step:a", StepStatus::Cached),
+                ("my-step:b", StepStatus::Succeeded)
+            ]
+        );
+        let read_log =
+            |name: &str| std::fs::read_to_string(root.join(name)).expect("failed to read log file");
+        assert_eq!(read_log("a.log"), "run\n");
+        assert_eq!(read_log("b.log"), "run\nrun\nrun\n");
+    }
+
+    #[test]
+    fn run_cache_up_to_date() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let step_config = ServiceStepConfig {
+            check: ScriptConfig::Multiline(String::from("[ -f ../../a.log ]")),
+            run: ScriptConfig::Multiline(String::from("echo run >> ../../a.log")),
+            ..Default::default()
+        };
+        let plan = Plan::new(vec![vec![create_step(root, "a", "my-step", &step_config)]]);
+
+        let executor = LocalExecutor::new(root);
+        let cache = LocalCache::new(root.join("cache"));
+        let fingerprints: HashMap<String, String> =
+            vec![(String::from("my-step:a"), String::from("abcd"))]
+                .into_iter()
+                .collect();
+
+        // Up to date results didn't run anything, so they are not cached
+        std::fs::write(root.join("a.log"), "").expect("failed to write file");
+        let statuses = [StepStatus::UpToDate, StepStatus::Succeeded];
+        for status in statuses.iter() {
+            let results = Scheduler::new(&executor)
+                .with_cache(&cache)
+                .with_fingerprints(fingerprints.clone())
+                .with_on_changed("my-step", StepOnChanged::CheckFirst)
+                .run(&plan)
+                .expect("failed to run plan");
+            assert_eq!(results[0].status, *status);
+            std::fs::remove_file(root.join("a.log")).expect("failed to remove file");
+        }
+    }
+
+    #[test]
+    fn run_cache_layers() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let plan = Plan::new(vec![vec![create_step(
+            root,
+            "a",
+            "my-step",
+            &run_config("echo run >> ../../a.log"),
+        )]]);
+
+        let executor = LocalExecutor::new(root);
+        let local_cache = LocalCache::new(root.join("local"));
+        let shared_cache = SharedCache::new(root.join("shared"));
+        let fingerprints: HashMap<String, String> =
+            vec![(String::from("my-step:a"), String::from("abcd"))]
+                .into_iter()
+                .collect();
+
+        // Populate the shared cache only
+        Scheduler::new(&executor)
+            .with_cache(&shared_cache)
+            .with_fingerprints(fingerprints.clone())
+            .run(&plan)
+            .expect("failed to run plan");
+        assert!(local_cache
+            .get("abcd", None)
+            .expect("failed to read cache")
+            .is_none());
+
+        let results = Scheduler::new(&executor)
+            .with_cache(&local_cache)
+            .with_cache(&shared_cache)
+            .with_fingerprints(fingerprints)
+            .run(&plan)
+            .expect("failed to run plan");
+
+        assert_eq!(results[0].status, StepStatus::Cached);
+        assert!(local_cache
+            .get("abcd", None)
+            .expect("failed to read cache")
+            .is_some());
+    }
+
+    #[test]
+    fn run_artifacts() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let build_config = ServiceStepConfig {
+            run: ScriptConfig::Multiline(String::from("mkdir -p dist\necho app > dist/app")),
+            outputs: vec![String::from("dist")],
+            ..Default::default()
+        };
+        let plan = Plan::new(vec![
+            vec![create_step(root, "a", "build", &build_config)],
+            vec![create_step(
+                root,
+                "b",
+                "my-step",
+                &run_config("cp \"$ORCS_ARTIFACTS_DIR/build/a/dist/app\" app"),
+            )],
+        ]);
+
+        let executor = LocalExecutor::new(root);
+        let artifacts = ArtifactStore::new(root, root.join("artifacts"));
+        let results = Scheduler::new(&executor)
+            .with_artifacts(&artifacts)
+            .run(&plan)
+            .expect("failed to run plan");
+
+        assert!(results.iter().all(StepResult::success));
+        assert_eq!(
+            std::fs::read_to_string(service_path(root, "b").join("app"))
+                .expect("failed to read file"),
+            "app\n"
+        );
+    }
+
+    #[test]
+    fn run_missing_output() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let build_config = ServiceStepConfig {
+            run: ScriptConfig::Multiline(String::from("true")),
+            outputs: vec![String::from("dist")],
+            ..Default::default()
+        };
+        let plan = Plan::new(vec![vec![create_step(root, "a", "build", &build_config)]]);
+
+        let executor = LocalExecutor::new(root);
+        let artifacts = ArtifactStore::new(root, root.join("artifacts"));
+        let results = Scheduler::new(&executor)
+            .with_artifacts(&artifacts)
+            .run(&plan)
+            .expect("failed to run plan");
+
+        assert_eq!(results[0].status, StepStatus::Failed);
+        assert_eq!(
+            results[0].run.as_ref().expect("missing run output").stderr,
+            b"'build:a' did not produce output 'dist'\n"
+        );
+    }
+
+    #[test]
+    fn run_values() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let build = create_step(
+            root,
+            "a",
+            "my-step",
+            &run_config("echo digest=sha256:abcd >> \"$ORCS_OUTPUT\""),
+        );
+        let mut deploy = create_step(
+            root,
+            "b",
+            "my-step",
+            &run_config("echo \"$ORCS_OUTPUT_MY_STEP_A_DIGEST\" >> ../../digest.log"),
+        );
+        deploy.name = String::from("deploy:b");
+        let plan = Plan::new(vec![vec![build], vec![deploy]]).with_dependencies(
+            vec![(String::from("deploy:b"), vec![String::from("my-step:a")])]
+                .into_iter()
+                .collect(),
+        );
+
+        let executor = LocalExecutor::new(root);
+        let artifacts = ArtifactStore::new(root, root.join("artifacts"));
+        let cache = LocalCache::new(root.join("cache"));
+        let fingerprints: HashMap<String, String> =
+            vec![(String::from("my-step:a"), String::from("abcd"))]
+                .into_iter()
+                .collect();
+
+        // The second run restores the values from the cache
+        for status in [StepStatus::Succeeded, StepStatus::Cached].iter() {
+            let results = Scheduler::new(&executor)
+                .with_artifacts(&artifacts)
+                .with_cache(&cache)
+                .with_fingerprints(fingerprints.clone())
+                .run(&plan)
+                .expect("failed to run plan");
+            assert_eq!(results[0].status, *status);
+            assert_eq!(
+                results[0].values.get("digest").map(String::as_str),
+                Some("sha256:abcd")
+            );
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(root.join("digest.log")).expect("failed to read file"),
+            "sha256:abcd\nsha256:abcd\n"
+        );
+    }
+
+    #[test]
+    fn run_values_up_to_date() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let step_config = ServiceStepConfig {
+            check: ScriptConfig::Multiline(String::from("exit 0")),
+            run: ScriptConfig::Multiline(String::from(
+                "echo digest=sha256:abcd >> \"$ORCS_OUTPUT\"",
+            )),
+            ..Default::default()
+        };
+        let plan = Plan::new(vec![vec![create_step(root, "a", "my-step", &step_config)]]);
+
+        let executor = LocalExecutor::new(root);
+        let artifacts = ArtifactStore::new(root, root.join("artifacts"));
+
+        // The run script is skipped on the second run, which keeps the values
+        // of the first one
+        let runs = [
+            (StepOnChanged::Run, StepStatus::Succeeded),
+            (StepOnChanged::CheckFirst, StepStatus::UpToDate),
+        ];
+        for (on_changed, status) in runs.iter() {
+            let results = Scheduler::new(&executor)
+                .with_artifacts(&artifacts)
+                .with_on_changed("my-step", *on_changed)
+                .run(&plan)
+                .expect("failed to run plan");
+            assert_eq!(results[0].status, *status);
+            assert_eq!(
+                results[0].values.get("digest").map(String::as_str),
+                Some("sha256:abcd")
+            );
+        }
+    }
+
+    #[test]
+    fn run_env() {
+        let project_dir = tempdir().expect("failed to create a temporary project folder");
+        let root = project_dir.path();
+        let plan = Plan::new(vec![vec![create_step(
+            root,
+            "a",
+            "my-step",
+            &run_config("echo \"$ORCS_SERVICE $ORCS_STEP $REGION\""),
+        )]]);
+
+        let executor = LocalExecutor::new(root);
+        let results = Scheduler::new(&executor)
+            .with_env(
+                vec![(String::from("REGION"), String::from("eu-west-1"))]
+                    .into_iter()
+                    .collect(),
+            )
+            .run(&plan)
+            .expect("failed to run plan");
+
+        assert_eq!(
+            results[0].run.as_ref().expect("missing run output").stdout,
+            b"a my-step eu-west-1\n"
+        );
+    }
+}
@@ -1,7 +1,19 @@
 use crate::config::{
-    RecipeConfig, RecipeStepConfig, ScriptConfig, ServiceConfig, ServiceStepConfig,
+    RecipeConfig, RecipeRef, RecipeStepConfig, ScriptConfig, ServiceConfig, ServiceStepConfig,
 };
-use std::collections::HashMap;
+use crate::project::{RECIPE_FOLDER, SERVICE_CONFIG_FILENAME, SERVICE_FOLDER};
+use crate::{template::render, Error, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Placeholder for the current service in dependencies
+const SELF_PLACEHOLDER: &str = "self";
+/// Environment variable containing the name of the service
+const SERVICE_VAR: &str = "ORCS_SERVICE";
+/// Environment variable containing the name of the step
+const STEP_VAR: &str = "ORCS_STEP";
+/// Empty script for steps created from recipes
+static NO_SCRIPT: ScriptConfig = ScriptConfig::None;
 
 /// Service
 ///
@@ -11,6 +23,12 @@ pub struct Service {
     /// Name of the service
     pub name: String,
 
+    /// Names of the recipes used by the service
+    recipes: Vec<String>,
+
+    /// Glob patterns for additional inputs of the service
+    inputs: Vec<String>,
+
     /// Steps and their internal representation.
     ///
     /// During a run, we need to retrieve all `ServiceStep` pairs that match
@@ -24,13 +42,28 @@ impl Service {
     /// This returns a builder to process the recipes mentioned in the
     /// configuration file, as the final `Service` struct is not aware of the
     /// recipes.
+    #[allow(clippy::redundant_field_names)]
     pub fn from_config<'a, 'b>(name: &'a str, config: &'b ServiceConfig) -> ServiceBuilder<'a, 'b> {
         ServiceBuilder {
             name: name,
+            recipes: &config.recipes,
+            inherited_recipes: Default::default(),
+            inputs: &config.inputs,
+            container_image: config.container_image.as_ref(),
+            env: &config.env,
             steps: config
                 .steps
                 .iter()
-                .map(|(step_name, step_config)| (step_name, step_config.into()))
+                .map(|(step_name, step_config)| {
+                    let mut step_builder: ServiceStepBuilder = step_config.into();
+                    // Service-level values take precedence over recipes
+                    if step_builder.container_image.is_none() && config.container_image.is_some() {
+                        step_builder.container_image = config.container_image.as_ref();
+                        step_builder.sources.container_image = Source::Service;
+                    }
+                    step_builder.env.push(&config.env);
+                    (step_name, step_builder)
+                })
                 .collect(),
         }
     }
@@ -39,6 +72,137 @@ impl Service {
     pub fn get_step(&self, step_name: &str) -> Option<&ServiceStep> {
         self.steps.get(step_name)
     }
+
+    /// Names of the recipes used by the service
+    ///
+    /// This includes the recipes extended by the recipes of the service.
+    pub fn recipes(&self) -> &[String] {
+        &self.recipes
+    }
+
+    /// Glob patterns for additional inputs of the service
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    /// Iterate over all `ServiceStep` pairs of the service
+    pub fn steps(&self) -> impl Iterator<Item = &ServiceStep> {
+        self.steps.values()
+    }
+
+    /// Render the placeholders in the scripts of all pairs of the service
+    ///
+    /// `vars` returns the variables for a pair, such as
+    /// `Project::template_vars`.
+    ///
+    /// Pairs that cannot be rendered are kept as they are, and their errors
+    /// are returned sorted by pair name.
+    pub fn render<F>(&self, vars: F) -> (Service, Vec<Error>)
+    where
+        F: Fn(&ServiceStep) -> BTreeMap<String, String>,
+    {
+        let mut errors: BTreeMap<&String, Error> = Default::default();
+        let steps = self
+            .steps
+            .iter()
+            .map(|(step_name, step)| {
+                let step = match step.render(&vars(step)) {
+                    Ok(rendered) => rendered,
+                    Err(err) => {
+                        errors.insert(&step.name, err);
+                        step.clone()
+                    }
+                };
+                (step_name.clone(), step)
+            })
+            .collect();
+
+        let service = Service {
+            name: self.name.clone(),
+            recipes: self.recipes.clone(),
+            inputs: self.inputs.clone(),
+            steps,
+        };
+        (service, errors.into_values().collect())
+    }
+}
+
+impl fmt::Display for Service {
+    /// Show the resolved steps of the service, with the origin of each value
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let origin = |source: &Source| match source {
+            Source::None => String::from("not set"),
+            Source::Service => format!(
+                "from {}/{}/{}",
+                SERVICE_FOLDER, self.name, SERVICE_CONFIG_FILENAME
+            ),
+            Source::Recipe(name) => format!("from {}/{}.toml", RECIPE_FOLDER, name),
+        };
+
+        writeln!(f, "service: {}", self.name)?;
+        if !self.recipes.is_empty() {
+            writeln!(f, "recipes: {}", self.recipes.join(", "))?;
+        }
+
+        let mut steps: Vec<&ServiceStep> = self.steps.values().collect();
+        steps.sort_by(|a, b| a.step_name.cmp(&b.step_name));
+        for step in steps {
+            let sources = step.sources();
+            writeln!(f)?;
+            writeln!(f, "step {}:", step.step_name)?;
+
+            if step.depends_on.is_empty() {
+                writeln!(f, "  depends_on: none")?;
+            } else {
+                writeln!(f, "  depends_on:")?;
+                for (dependency, source) in step.depends_on.iter().zip(&sources.depends_on) {
+                    writeln!(f, "    {} ({})", dependency, origin(source))?;
+                }
+            }
+
+            let scripts = [
+                ("check", &step.check, &sources.check),
+                ("run", &step.run, &sources.run),
+            ];
+            for (label, script, source) in scripts.iter() {
+                match script {
+                    Script::Script(body) => {
+                        writeln!(f, "  {} ({}):", label, origin(source))?;
+                        for line in body.lines() {
+                            writeln!(f, "    {}", line)?;
+                        }
+                    }
+                    Script::Override(value) => {
+                        writeln!(f, "  {}: {} ({})", label, value, origin(source))?
+                    }
+                    Script::None => writeln!(f, "  {}: none", label)?,
+                }
+            }
+
+            match &step.container_image {
+                Some(image) => writeln!(
+                    f,
+                    "  container_image: {} ({})",
+                    image,
+                    origin(&sources.container_image)
+                )?,
+                None => writeln!(f, "  container_image: project default")?,
+            }
+
+            if step.outputs.is_empty() {
+                writeln!(f, "  outputs: none")?;
+            } else {
+                writeln!(
+                    f,
+                    "  outputs: {} ({})",
+                    step.outputs.join(", "),
+                    origin(&sources.outputs)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// Builder for a Service
@@ -48,6 +212,18 @@ impl Service {
 pub struct ServiceBuilder<'a, 'b> {
     name: &'a str,
 
+    recipes: &'b [RecipeRef],
+
+    /// Names of recipes used by the service without being listed in its
+    /// configuration
+    inherited_recipes: Vec<String>,
+
+    inputs: &'b [String],
+
+    container_image: Option<&'b String>,
+
+    env: &'b BTreeMap<String, String>,
+
     steps: HashMap<&'b String, ServiceStepBuilder<'b>>,
 }
 
@@ -56,18 +232,66 @@ impl<'a, 'b> ServiceBuilder<'a, 'b> {
     ///
     /// If a step doesn't exist in the `ServiceBuilder`, this will inject it
     /// with the values from the `RecipeConfig`.
+    ///
+    /// The name of the recipe isn't known here, so the origin of the values
+    /// isn't tracked. Use `with_named_recipe` to track it.
     pub fn with_recipe(&mut self, recipe: &'b RecipeConfig) -> &mut ServiceBuilder<'a, 'b> {
+        self.merge_recipe(None, recipe)
+    }
+
+    /// Inject a recipe into the builder, with its name
+    ///
+    /// This works like `with_recipe`, but the name of the recipe is recorded
+    /// as the source of its values. Recipes that are not listed in the
+    /// service configuration, such as recipes extended by the recipes of the
+    /// service, are recorded as used by the service.
+    pub fn with_named_recipe(
+        &mut self,
+        recipe_name: &str,
+        recipe: &'b RecipeConfig,
+    ) -> &mut ServiceBuilder<'a, 'b> {
+        if !self
+            .recipes
+            .iter()
+            .any(|recipe| recipe.name() == recipe_name)
+            && !self
+                .inherited_recipes
+                .iter()
+                .any(|name| name == recipe_name)
+        {
+            self.inherited_recipes.push(recipe_name.to_string());
+        }
+
+        self.merge_recipe(Some(recipe_name), recipe)
+    }
+
+    /// Inject a recipe into the builder, with its name if known
+    fn merge_recipe(
+        &mut self,
+        recipe_name: Option<&str>,
+        recipe: &'b RecipeConfig,
+    ) -> &mut ServiceBuilder<'a, 'b> {
         for (step_name, step_config) in &recipe.steps {
-            // Case 1: the step doesn't exist, so we just override it
+            // Case 1: the step doesn't exist, so we start from an empty step
             if !self.steps.contains_key(step_name) {
-                self.steps.insert(step_name, step_config.into());
+                let mut step_builder = ServiceStepBuilder::default();
+                // Service-level values take precedence over recipes
+                if self.container_image.is_some() {
+                    step_builder.container_image = self.container_image;
+                    step_builder.sources.container_image = Source::Service;
+                }
+                step_builder.env.push(self.env);
+                self.steps.insert(step_name, step_builder);
             }
             // Case 2: the service exists, but check or run are not set
             let step_builder = self
                 .steps
                 .get_mut(step_name)
                 .expect("failed to get step builder");
-            step_builder.with_recipe(step_config);
+            match recipe_name {
+                Some(recipe_name) => step_builder.with_named_recipe(recipe_name, step_config),
+                None => step_builder.with_recipe(step_config),
+            };
         }
 
         self
@@ -77,6 +301,13 @@ impl<'a, 'b> ServiceBuilder<'a, 'b> {
     pub fn build(self) -> Service {
         Service {
             name: self.name.to_string(),
+            recipes: self
+                .recipes
+                .iter()
+                .map(|recipe| recipe.name().to_string())
+                .chain(self.inherited_recipes.iter().cloned())
+                .collect(),
+            inputs: self.inputs.to_vec(),
             steps: self
                 .steps
                 .iter()
@@ -91,73 +322,298 @@ impl<'a, 'b> ServiceBuilder<'a, 'b> {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 /// Unique step:service pair
 pub struct ServiceStep {
     /// Name of the pair in 'step:service' format.
     pub name: String,
 
+    /// Name of the step
+    pub step_name: String,
+
+    /// Name of the service
+    pub service_name: String,
+
     depends_on: Vec<String>,
 
     check: Script,
     run: Script,
+
+    container_image: Option<String>,
+
+    outputs: Vec<String>,
+
+    env: BTreeMap<String, String>,
+
+    sources: StepSources,
+}
+
+impl ServiceStep {
+    /// List of step:service pairs that this pair depends on
+    pub fn depends_on(&self) -> &[String] {
+        &self.depends_on
+    }
+
+    /// Script to run on a 'check'
+    pub fn check(&self) -> &Script {
+        &self.check
+    }
+
+    /// Script to run on a 'run'
+    pub fn run(&self) -> &Script {
+        &self.run
+    }
+
+    /// Container image for this pair, if it overrides the project's image
+    pub fn container_image(&self) -> Option<&str> {
+        self.container_image.as_deref()
+    }
+
+    /// Paths produced by a successful run, relative to the service folder
+    pub fn outputs(&self) -> &[String] {
+        &self.outputs
+    }
+
+    /// Environment variables from the service and recipe configurations
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    /// Origin of the values of this pair
+    pub fn sources(&self) -> &StepSources {
+        &self.sources
+    }
+
+    /// Render the placeholders in the check and run scripts of this pair
+    ///
+    /// See `Project::template_vars` for the variables available to scripts.
+    pub fn render(&self, vars: &BTreeMap<String, String>) -> Result<ServiceStep> {
+        let render_script = |script: &Script| -> Result<Script> {
+            Ok(match script {
+                Script::Script(body) => Script::Script(render(body, vars, &self.name)?),
+                script => script.clone(),
+            })
+        };
+
+        Ok(ServiceStep {
+            check: render_script(&self.check)?,
+            run: render_script(&self.run)?,
+            ..self.clone()
+        })
+    }
+
+    /// Environment variables for the scripts of this pair
+    ///
+    /// This adds the variables of the pair, as well as the `ORCS_SERVICE` and
+    /// `ORCS_STEP` variables, on top of `base`.
+    pub fn script_env(&self, base: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        let mut env = base.clone();
+        env.extend(self.env.clone());
+        env.insert(SERVICE_VAR.to_string(), self.service_name.clone());
+        env.insert(STEP_VAR.to_string(), self.step_name.clone());
+        env
+    }
+}
+
+/// Origin of a resolved value
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Source {
+    /// The value isn't set
+    #[default]
+    None,
+    /// The service configuration file
+    Service,
+    /// A recipe, by name
+    Recipe(String),
+}
+
+/// Origin of the values of a `ServiceStep`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StepSources {
+    pub check: Source,
+    pub run: Source,
+    pub container_image: Source,
+    pub outputs: Source,
+
+    /// Origin of each dependency, in the same order as `depends_on`
+    pub depends_on: Vec<Source>,
 }
 
 pub struct ServiceStepBuilder<'a> {
-    depends_on: Option<&'a Vec<String>>,
+    depends_on: Vec<(&'a String, Source)>,
     check: &'a ScriptConfig,
     run: &'a ScriptConfig,
+    container_image: Option<&'a String>,
+    outputs: &'a [String],
+    /// Environment variables, from the highest to the lowest precedence
+    env: Vec<&'a BTreeMap<String, String>>,
+    /// Origin of the values, except for dependencies
+    sources: StepSources,
+}
+
+impl<'a> Default for ServiceStepBuilder<'a> {
+    fn default() -> Self {
+        Self {
+            depends_on: Default::default(),
+            check: &NO_SCRIPT,
+            run: &NO_SCRIPT,
+            container_image: None,
+            outputs: &[],
+            env: Default::default(),
+            sources: Default::default(),
+        }
+    }
 }
 
 impl<'a> From<&'a ServiceStepConfig> for ServiceStepBuilder<'a> {
     fn from(config: &'a ServiceStepConfig) -> Self {
+        let source = |is_set: bool| {
+            if is_set {
+                Source::Service
+            } else {
+                Source::None
+            }
+        };
+
         Self {
-            depends_on: Some(&config.depends_on),
+            depends_on: config
+                .depends_on
+                .iter()
+                .map(|dependency| (dependency, Source::Service))
+                .collect(),
             check: &config.check,
             run: &config.run,
+            container_image: config.container_image.as_ref(),
+            outputs: &config.outputs,
+            env: vec![&config.env],
+            sources: StepSources {
+                check: source(!config.check.is_empty()),
+                run: source(!config.run.is_empty()),
+                container_image: source(config.container_image.is_some()),
+                outputs: source(!config.outputs.is_empty()),
+                depends_on: Default::default(),
+            },
         }
     }
 }
 
+/// Create a builder from a recipe step
+///
+/// The name of the recipe isn't known here, so the origin of the values isn't
+/// tracked. Use `with_named_recipe` on a default builder to track it.
 impl<'a> From<&'a RecipeStepConfig> for ServiceStepBuilder<'a> {
     fn from(config: &'a RecipeStepConfig) -> Self {
         Self {
-            depends_on: None,
+            depends_on: config
+                .depends_on
+                .iter()
+                .map(|dependency| (dependency, Source::None))
+                .collect(),
             check: &config.check,
             run: &config.run,
+            container_image: config.container_image.as_ref(),
+            outputs: &config.outputs,
+            env: vec![&config.env],
+            sources: Default::default(),
         }
     }
 }
 
 impl<'a> ServiceStepBuilder<'a> {
     /// Update the `ServiceStepBuilder` with values from a `RecipeStepConfig`
-    /// if the builder doesn't contain values for check or run and the recipe
-    /// does.
+    /// if the builder doesn't contain values for check, run, container
+    /// image or outputs and the recipe does.
+    ///
+    /// Dependencies from the recipe are added to the existing ones, and
+    /// environment variables from the recipe are added with a lower
+    /// precedence than the existing ones.
+    ///
+    /// The origin of the values from the recipe isn't tracked. Use
+    /// `with_named_recipe` to track it.
     pub fn with_recipe(&mut self, config: &'a RecipeStepConfig) -> &mut Self {
+        self.merge_recipe(Source::None, config)
+    }
+
+    /// Update the `ServiceStepBuilder` with values from a `RecipeStepConfig`,
+    /// recording the name of the recipe as their origin
+    ///
+    /// See `with_recipe` for the values taken from the recipe.
+    pub fn with_named_recipe(
+        &mut self,
+        recipe_name: &str,
+        config: &'a RecipeStepConfig,
+    ) -> &mut Self {
+        self.merge_recipe(Source::Recipe(recipe_name.to_string()), config)
+    }
+
+    /// Update the `ServiceStepBuilder` with values from a `RecipeStepConfig`,
+    /// with `source` as their origin
+    fn merge_recipe(&mut self, source: Source, config: &'a RecipeStepConfig) -> &mut Self {
         if self.check.is_empty() && !config.check.is_empty() {
             self.check = &config.check;
+            self.sources.check = source.clone();
         }
         if self.run.is_empty() && !config.run.is_empty() {
             self.run = &config.run;
+            self.sources.run = source.clone();
+        }
+        if self.container_image.is_none() && config.container_image.is_some() {
+            self.container_image = config.container_image.as_ref();
+            self.sources.container_image = source.clone();
         }
+        if self.outputs.is_empty() && !config.outputs.is_empty() {
+            self.outputs = &config.outputs;
+            self.sources.outputs = source.clone();
+        }
+        for dependency in &config.depends_on {
+            if !self.depends_on.iter().any(|(dep, _)| *dep == dependency) {
+                self.depends_on.push((dependency, source.clone()));
+            }
+        }
+        self.env.push(&config.env);
 
         self
     }
 
     /// Build into an owned `ServiceStep`
     pub fn build(&self, service_name: &str, step_name: &str) -> ServiceStep {
+        let mut depends_on: Vec<String> = Default::default();
+        let mut sources = self.sources.clone();
+        for (dep, source) in &self.depends_on {
+            // Replace the `self` placeholder with the current service
+            let dep = match dep.split_once(':') {
+                Some((dep_step, SELF_PLACEHOLDER)) => format!("{}:{}", dep_step, service_name),
+                _ => dep.to_string(),
+            };
+            if !depends_on.contains(&dep) {
+                depends_on.push(dep);
+                sources.depends_on.push(source.clone());
+            }
+        }
+
         ServiceStep {
             name: format!("{}:{}", step_name, service_name),
-            depends_on: match self.depends_on {
-                Some(deps) => deps.clone(),
-                None => Vec::new(),
-            },
+            step_name: step_name.to_string(),
+            service_name: service_name.to_string(),
+            depends_on,
             check: self.check.into(),
             run: self.run.into(),
+            container_image: self.container_image.cloned(),
+            outputs: self.outputs.to_vec(),
+            env: self
+                .env
+                .iter()
+                .rev()
+                .flat_map(|env| env.iter())
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            sources,
         }
     }
 }
 
+/// Resolved script for a check or run action
+#[allow(clippy::enum_variant_names)]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Script {
     Script(String),
@@ -211,7 +667,7 @@ mod tests {
     fn service_builder_with_recipe() {
         // Starting with a simple config
         let service_config = ServiceConfig {
-            recipes: vec![String::from("my-recipe")],
+            recipes: vec![RecipeRef::Name(String::from("my-recipe"))],
 
             ..Default::default()
         };
@@ -239,6 +695,7 @@ mod tests {
 
         // Assertions
         assert_eq!(service.name, "my-service");
+        assert_eq!(service.recipes(), ["my-recipe"]);
         assert_eq!(service_step.depends_on, Vec::new() as Vec<String>);
         assert_eq!(service_step.run, Script::Override(true));
         assert_eq!(service_step.check, Script::None);
@@ -248,7 +705,10 @@ mod tests {
     fn service_builder_with_recipe_2() {
         // Starting with a simple config
         let service_config = ServiceConfig {
-            recipes: vec![String::from("my-recipe1"), String::from("my-recipe2")],
+            recipes: vec![
+                RecipeRef::Name(String::from("my-recipe1")),
+                RecipeRef::Name(String::from("my-recipe2")),
+            ],
 
             ..Default::default()
         };
@@ -308,6 +768,389 @@ mod tests {
         assert_eq!(service_step2.check, Script::None);
     }
 
+    #[test]
+    fn service_builder_container_image() {
+        let service_config = ServiceConfig {
+            steps: vec![
+                (
+                    String::from("my-step1"),
+                    ServiceStepConfig {
+                        container_image: Some(String::from("service-step-image")),
+                        ..Default::default()
+                    },
+                ),
+                (String::from("my-step2"), ServiceStepConfig::default()),
+            ]
+            .into_iter()
+            .collect(),
+            container_image: Some(String::from("service-image")),
+            ..Default::default()
+        };
+        let recipe_config = RecipeConfig {
+            steps: vec![
+                (
+                    String::from("my-step1"),
+                    RecipeStepConfig {
+                        container_image: Some(String::from("recipe-image")),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    String::from("my-step2"),
+                    RecipeStepConfig {
+                        container_image: Some(String::from("recipe-image")),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    String::from("my-step3"),
+                    RecipeStepConfig {
+                        container_image: Some(String::from("recipe-image")),
+                        ..Default::default()
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+
+        let mut service_builder = Service::from_config("my-service", &service_config);
+        service_builder.with_recipe(&recipe_config);
+        let service = service_builder.build();
+
+        let image = |step_name: &str| {
+            service
+                .get_step(step_name)
+                .expect("failed to get step")
+                .container_image()
+                .map(str::to_string)
+        };
+        assert_eq!(image("my-step1").as_deref(), Some("service-step-image"));
+        assert_eq!(image("my-step2").as_deref(), Some("service-image"));
+        assert_eq!(image("my-step3").as_deref(), Some("service-image"));
+    }
+
+    #[test]
+    fn service_builder_recipe_container_image() {
+        let service_config = ServiceConfig {
+            steps: vec![(String::from("my-step"), ServiceStepConfig::default())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let recipe_config1 = RecipeConfig {
+            steps: vec![(
+                String::from("my-step"),
+                RecipeStepConfig {
+                    container_image: Some(String::from("recipe-image1")),
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let recipe_config2 = RecipeConfig {
+            steps: vec![(
+                String::from("my-step"),
+                RecipeStepConfig {
+                    container_image: Some(String::from("recipe-image2")),
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+
+        // The first recipe injected takes precedence
+        let mut service_builder = Service::from_config("my-service", &service_config);
+        service_builder.with_recipe(&recipe_config1);
+        service_builder.with_recipe(&recipe_config2);
+        let service = service_builder.build();
+
+        let service_step = service.get_step("my-step").expect("failed to get step");
+        assert_eq!(service_step.container_image(), Some("recipe-image1"));
+    }
+
+    #[test]
+    fn service_builder_outputs() {
+        let service_config = ServiceConfig {
+            steps: vec![
+                (
+                    String::from("my-step1"),
+                    ServiceStepConfig {
+                        outputs: vec![String::from("service-output")],
+                        ..Default::default()
+                    },
+                ),
+                (String::from("my-step2"), ServiceStepConfig::default()),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let recipe_step_config = || RecipeStepConfig {
+            outputs: vec![String::from("recipe-output")],
+            ..Default::default()
+        };
+        let recipe_config = RecipeConfig {
+            steps: vec![
+                (String::from("my-step1"), recipe_step_config()),
+                (String::from("my-step2"), recipe_step_config()),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+
+        let mut service_builder = Service::from_config("my-service", &service_config);
+        service_builder.with_recipe(&recipe_config);
+        let service = service_builder.build();
+
+        let outputs = |step_name: &str| {
+            service
+                .get_step(step_name)
+                .expect("failed to get step")
+                .outputs()
+                .to_vec()
+        };
+        assert_eq!(outputs("my-step1"), ["service-output"]);
+        assert_eq!(outputs("my-step2"), ["recipe-output"]);
+    }
+
+    #[test]
+    fn service_builder_env() {
+        let env = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        let service_config = ServiceConfig {
+            steps: vec![(
+                String::from("my-step1"),
+                ServiceStepConfig {
+                    env: env(&[("A", "service-step")]),
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+            env: env(&[("A", "service"), ("B", "service")]),
+            ..Default::default()
+        };
+        let recipe_step_config = || RecipeStepConfig {
+            env: env(&[("A", "recipe"), ("B", "recipe"), ("C", "recipe")]),
+            ..Default::default()
+        };
+        let recipe_config = RecipeConfig {
+            steps: vec![
+                (String::from("my-step1"), recipe_step_config()),
+                (String::from("my-step2"), recipe_step_config()),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+
+        let mut service_builder = Service::from_config("my-service", &service_config);
+        service_builder.with_recipe(&recipe_config);
+        let service = service_builder.build();
+
+        let step1 = service.get_step("my-step1").expect("failed to get step");
+        assert_eq!(
+            step1.env(),
+            &env(&[("A", "service-step"), ("B", "service"), ("C", "recipe")])
+        );
+        let step2 = service.get_step("my-step2").expect("failed to get step");
+        assert_eq!(
+            step2.env(),
+            &env(&[("A", "service"), ("B", "service"), ("C", "recipe")])
+        );
+
+        let script_env = step1.script_env(&env(&[("C", "project"), ("D", "project")]));
+        assert_eq!(
+            script_env,
+            env(&[
+                ("A", "service-step"),
+                ("B", "service"),
+                ("C", "recipe"),
+                ("D", "project"),
+                ("ORCS_SERVICE", "my-service"),
+                ("ORCS_STEP", "my-step1"),
+            ])
+        );
+    }
+
+    #[test]
+    fn service_builder_depends_on() {
+        let service_config = ServiceConfig {
+            steps: vec![(
+                String::from("deploy"),
+                ServiceStepConfig {
+                    depends_on: vec![String::from("build:other"), String::from("test:self")],
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let recipe_config = RecipeConfig {
+            steps: vec![(
+                String::from("deploy"),
+                RecipeStepConfig {
+                    depends_on: vec![String::from("build:self"), String::from("test:self")],
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+
+        let mut service_builder = Service::from_config("my-service", &service_config);
+        service_builder.with_recipe(&recipe_config);
+        let service = service_builder.build();
+
+        let step = service.get_step("deploy").expect("failed to get step");
+        assert_eq!(
+            step.depends_on(),
+            ["build:other", "test:my-service", "build:my-service"]
+        );
+    }
+
+    #[test]
+    fn service_sources() {
+        let service_config = ServiceConfig {
+            steps: vec![(
+                String::from("deploy"),
+                ServiceStepConfig {
+                    depends_on: vec![String::from("build:other")],
+                    run: ScriptConfig::Multiline(String::from("make deploy\nmake notify")),
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+            recipes: vec![RecipeRef::Name(String::from("my-recipe"))],
+            container_image: Some(String::from("service-image")),
+            ..Default::default()
+        };
+        let recipe_config = RecipeConfig {
+            steps: vec![
+                (
+                    String::from("deploy"),
+                    RecipeStepConfig {
+                        depends_on: vec![String::from("build:self")],
+                        check: ScriptConfig::Boolean(true),
+                        run: ScriptConfig::Multiline(String::from("recipe-run")),
+                        outputs: vec![String::from("dist")],
+                        ..Default::default()
+                    },
+                ),
+                (String::from("build"), RecipeStepConfig::default()),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+
+        let mut service_builder = Service::from_config("my-service", &service_config);
+        service_builder.with_named_recipe("my-recipe", &recipe_config);
+        let service = service_builder.build();
+
+        let recipe = || Source::Recipe(String::from("my-recipe"));
+        let step = service.get_step("deploy").expect("failed to get step");
+        assert_eq!(
+            step.sources(),
+            &StepSources {
+                check: recipe(),
+                run: Source::Service,
+                container_image: Source::Service,
+                outputs: recipe(),
+                depends_on: vec![Source::Service, recipe()],
+            }
+        );
+        let step = service.get_step("build").expect("failed to get step");
+        assert_eq!(step.sources().run, Source::None);
+        assert_eq!(step.sources().container_image, Source::Service);
+
+        assert_eq!(
+            service.to_string(),
+            "service: my-service
+recipes: my-recipe
+
+step build:
+  depends_on: none
+  check: none
+  run: none
+  container_image: service-image (from srv/my-service/orcs.toml)
+  outputs: none
+
+step deploy:
+  depends_on:
+    build:other (from srv/my-service/orcs.toml)
+    build:my-service (from rcp/my-recipe.toml)
+  check: true (from rcp/my-recipe.toml)
+  run (from srv/my-service/orcs.toml):
+    make deploy
+    make notify
+  container_image: service-image (from srv/my-service/orcs.toml)
+  outputs: dist (from rcp/my-recipe.toml)
+"
+        );
+    }
+
+    #[test]
+    fn service_render() {
+        let service_config = ServiceConfig {
+            steps: vec![(
+                String::from("deploy"),
+                ServiceStepConfig {
+                    run: ScriptConfig::Multiline(String::from("make deploy-{{ service.name }}")),
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let service = Service::from_config("my-service", &service_config).build();
+
+        let (rendered, errors) = service.render(|step| {
+            vec![(String::from("service.name"), step.service_name.clone())]
+                .into_iter()
+                .collect()
+        });
+
+        assert!(errors.is_empty());
+        let step = rendered.get_step("deploy").expect("failed to get step");
+        assert_eq!(
+            step.run(),
+            &Script::Script(String::from("make deploy-my-service"))
+        );
+        assert_eq!(step.sources().run, Source::Service);
+
+        // Pairs that cannot be rendered are kept as they are
+        let (rendered, errors) = service.render(|_| Default::default());
+
+        match errors.as_slice() {
+            [Error::UndefinedVariable { name, variable }] => {
+                assert_eq!(name, "deploy:my-service");
+                assert_eq!(variable, "service.name");
+            }
+            _ => panic!("expected an undefined variable error"),
+        }
+        let step = rendered.get_step("deploy").expect("failed to get step");
+        assert_eq!(
+            step.run(),
+            &Script::Script(String::from("make deploy-{{ service.name }}"))
+        );
+    }
+
     #[test]
     fn service_step_builder() {
         // Starting with a simple config
@@ -324,6 +1167,8 @@ mod tests {
 
         // Assertions
         assert_eq!(step.name, "my-step:my-service");
+        assert_eq!(step.step_name, "my-step");
+        assert_eq!(step.service_name, "my-service");
         assert_eq!(step.run, Script::Override(true));
         assert_eq!(step.check, Script::None);
     }
@@ -352,6 +1197,16 @@ mod tests {
         assert_eq!(step.name, "my-step:my-service");
         assert_eq!(step.run, Script::Override(true));
         assert_eq!(step.check, Script::None);
+        assert_eq!(step.sources().run, Source::None);
+
+        // Named recipes are recorded as the origin of the values
+        let mut step_builder: ServiceStepBuilder = (&step_config).into();
+        step_builder.with_named_recipe("my-recipe", &recipe_config);
+        let step = step_builder.build("my-service", "my-step");
+        assert_eq!(
+            step.sources().run,
+            Source::Recipe(String::from("my-recipe"))
+        );
     }
 
     #[test]
@@ -367,6 +1222,7 @@ mod tests {
         let recipe_config2 = RecipeStepConfig {
             run: ScriptConfig::Boolean(false),
             check: ScriptConfig::Boolean(false),
+            ..Default::default()
         };
 
         // Create the step builder
@@ -386,6 +1242,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn script_from_multiline() {
         let value = "a\nb\nc";
         let config = ScriptConfig::Multiline(String::from("a\nb\nc"));
@@ -399,6 +1256,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn script_from_array() {
         let value = "a\nb\nc";
         let config = ScriptConfig::Array(vec![
@@ -416,6 +1274,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn script_from_boolean() {
         let value = true;
         let config = ScriptConfig::Boolean(true);
@@ -0,0 +1,163 @@
+use crate::{Error, Result};
+use std::collections::BTreeMap;
+
+/// Start of a placeholder
+const OPEN: &str = "{{";
+/// End of a placeholder
+const CLOSE: &str = "}}";
+
+/// Render the placeholders of a script
+///
+/// Placeholders are variable names between double curly braces, such as
+/// `{{ service.name }}`, and are replaced by the value of the variable. A
+/// `{{` preceded by a backslash is kept as is, without the backslash, for
+/// scripts that need literal double curly braces.
+///
+/// `name` is the step:service pair owning the script, used for errors.
+pub(crate) fn render(
+    template: &str,
+    vars: &BTreeMap<String, String>,
+    name: &str,
+) -> Result<String> {
+    render_matching(template, vars, name, None)
+}
+
+/// Render the placeholders of a script for variables starting with `prefix`
+///
+/// Other placeholders and escaped placeholders are kept as is, so that they
+/// can be rendered later with `render`.
+pub(crate) fn render_prefix(
+    template: &str,
+    prefix: &str,
+    vars: &BTreeMap<String, String>,
+    name: &str,
+) -> Result<String> {
+    render_matching(template, vars, name, Some(prefix))
+}
+
+/// Render the placeholders of a script, optionally limited to variables
+/// starting with `prefix`
+fn render_matching(
+    template: &str,
+    vars: &BTreeMap<String, String>,
+    name: &str,
+    prefix: Option<&str>,
+) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(index) = rest.find(OPEN) {
+        // Escaped placeholder
+        if rest[..index].ends_with('\\') {
+            match prefix {
+                Some(_) => output.push_str(&rest[..index]),
+                None => output.push_str(&rest[..index - 1]),
+            }
+            output.push_str(OPEN);
+            rest = &rest[index + OPEN.len()..];
+            continue;
+        }
+
+        output.push_str(&rest[..index]);
+        let placeholder = &rest[index + OPEN.len()..];
+        let end = placeholder
+            .find(CLOSE)
+            .ok_or_else(|| Error::UnclosedPlaceholder {
+                name: name.to_string(),
+            })?;
+        let variable = placeholder[..end].trim();
+        if prefix.is_some_and(|prefix| !variable.starts_with(prefix)) {
+            // Leave the placeholder for a later render
+            output.push_str(&rest[index..index + OPEN.len() + end + CLOSE.len()]);
+        } else {
+            let value = vars.get(variable).ok_or_else(|| Error::UndefinedVariable {
+                name: name.to_string(),
+                variable: variable.to_string(),
+            })?;
+            output.push_str(value);
+        }
+        rest = &placeholder[end + CLOSE.len()..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> BTreeMap<String, String> {
+        vec![
+            (String::from("service.name"), String::from("my-service")),
+            (String::from("env.REGION"), String::from("eu-west-1")),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn render_variables() {
+        let output = render(
+            "deploy {{ service.name }} --region {{env.REGION}}\necho done",
+            &vars(),
+            "deploy:my-service",
+        )
+        .expect("failed to render template");
+
+        assert_eq!(output, "deploy my-service --region eu-west-1\necho done");
+    }
+
+    #[test]
+    fn render_escaped() {
+        let output = render(
+            "docker inspect -f '\\{{.Id}}' {{ service.name }}",
+            &vars(),
+            "build:my-service",
+        )
+        .expect("failed to render template");
+
+        assert_eq!(output, "docker inspect -f '{{.Id}}' my-service");
+    }
+
+    #[test]
+    fn render_undefined() {
+        match render("echo {{ service.path }}", &vars(), "build:my-service") {
+            Err(Error::UndefinedVariable { name, variable }) => {
+                assert_eq!(name, "build:my-service");
+                assert_eq!(variable, "service.path");
+            }
+            _ => panic!("expected an undefined variable error"),
+        }
+    }
+
+    #[test]
+    fn render_unclosed() {
+        match render("echo {{ service.name", &vars(), "build:my-service") {
+            Err(Error::UnclosedPlaceholder { name }) => assert_eq!(name, "build:my-service"),
+            _ => panic!("expected an unclosed placeholder error"),
+        }
+    }
+
+    #[test]
+    fn render_prefix_only() {
+        let output = render_prefix(
+            "echo {{ params.file }} {{ service.name }} \\{{ params.file }}",
+            "params.",
+            &vec![(String::from("params.file"), String::from("Dockerfile"))]
+                .into_iter()
+                .collect(),
+            "build:my-service",
+        )
+        .expect("failed to render template");
+        assert_eq!(
+            output,
+            "echo Dockerfile {{ service.name }} \\{{ params.file }}"
+        );
+
+        // The remaining placeholders are rendered later
+        let output =
+            render(&output, &vars(), "build:my-service").expect("failed to render template");
+        assert_eq!(output, "echo Dockerfile my-service {{ params.file }}");
+    }
+}
@@ -0,0 +1,41 @@
+//! Fixtures shared between the tests of the different modules
+
+use crate::config::{ScriptConfig, ServiceStepConfig};
+use crate::executor::service_path;
+use crate::service::ServiceStepBuilder;
+use crate::ServiceStep;
+use std::fs::create_dir_all;
+use std::path::Path;
+
+/// Build a step:service pair from a step configuration
+pub(crate) fn build_step(
+    service_name: &str,
+    step_name: &str,
+    config: &ServiceStepConfig,
+) -> ServiceStep {
+    let step_builder: ServiceStepBuilder = config.into();
+    step_builder.build(service_name, step_name)
+}
+
+/// Build a step:service pair from a step configuration and create its
+/// service folder in the project at `root`
+pub(crate) fn create_step<P>(
+    root: P,
+    service_name: &str,
+    step_name: &str,
+    config: &ServiceStepConfig,
+) -> ServiceStep
+where
+    P: AsRef<Path>,
+{
+    create_dir_all(service_path(root, service_name)).expect("failed to create service folder");
+    build_step(service_name, step_name, config)
+}
+
+/// Configuration of a step with only a run script
+pub(crate) fn run_config(run: &str) -> ServiceStepConfig {
+    ServiceStepConfig {
+        run: ScriptConfig::Multiline(run.to_string()),
+        ..Default::default()
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{Error, Result};
 use serde::de::DeserializeOwned;
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, File};
 use std::io::prelude::*;
 use std::path::PathBuf;
 
@@ -37,6 +38,78 @@ where
     })
 }
 
+/// Load a dotenv file and return an appropriate error if the file cannot be
+/// loaded.
+pub fn load_env_file<P>(path: P) -> Result<BTreeMap<String, String>>
+where
+    P: Into<PathBuf>,
+{
+    let path = path.into();
+
+    let data = read_to_string(&path).map_err(|source| Error::CannotReadEnvFile {
+        path: path.clone(),
+        source,
+    })?;
+
+    parse_env(&data).map_err(|line| Error::InvalidEnvFile { path, line })
+}
+
+/// Parse `KEY=value` lines, in the dotenv format
+///
+/// Empty lines and lines starting with `#` are ignored, keys can be prefixed
+/// with `export`, and values can be wrapped in single or double quotes. When
+/// a key is present multiple times, the last value wins.
+///
+/// This returns the first invalid line on failure.
+pub fn parse_env(data: &str) -> std::result::Result<BTreeMap<String, String>, String> {
+    let mut env: BTreeMap<String, String> = Default::default();
+
+    for line in data.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match trimmed.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(line.to_string()),
+        };
+        let key = key.strip_prefix("export ").unwrap_or(key).trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(line.to_string());
+        }
+
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|quote| {
+                value
+                    .strip_prefix(*quote)
+                    .and_then(|value| value.strip_suffix(*quote))
+            })
+            .unwrap_or(value);
+        env.insert(key.to_string(), value.to_string());
+    }
+
+    Ok(env)
+}
+
+/// Transform a value into an environment variable name
+///
+/// Letters are uppercased, and any character other than ASCII letters and
+/// digits is replaced by an underscore.
+pub fn env_var_name(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -66,4 +139,55 @@ mod tests {
         let result: TestConfigData = load_config(&path).expect("failed to open file");
         assert_eq!(result, value);
     }
+
+    #[test]
+    fn test_parse_env() {
+        let data = "
+            # comment
+            A=1
+            export B = two words
+            C=\"quoted # value\"
+            D='single'
+            E=a=b
+            A=3
+        ";
+
+        let env = parse_env(data).expect("failed to parse env");
+        assert_eq!(
+            env.iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("A", "3"),
+                ("B", "two words"),
+                ("C", "quoted # value"),
+                ("D", "single"),
+                ("E", "a=b")
+            ]
+        );
+
+        assert_eq!(parse_env("A=1\nnot valid"), Err(String::from("not valid")));
+        assert_eq!(parse_env("=1"), Err(String::from("=1")));
+    }
+
+    #[test]
+    fn test_load_env_file() {
+        let dir = tempdir().expect("failed to create temporary folder");
+        let path = dir.path().join(".env");
+        std::fs::write(&path, "A=1\n").expect("failed to write file");
+
+        let env = load_env_file(&path).expect("failed to load env file");
+        assert_eq!(env.get("A").map(String::as_str), Some("1"));
+
+        match load_env_file(dir.path().join("missing.env")) {
+            Err(Error::CannotReadEnvFile { .. }) => (),
+            _ => panic!("expected a cannot read env file error"),
+        }
+    }
+
+    #[test]
+    fn test_env_var_name() {
+        assert_eq!(env_var_name("build:my-api"), "BUILD_MY_API");
+        assert_eq!(env_var_name("image.digest2"), "IMAGE_DIGEST2");
+    }
 }